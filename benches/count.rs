@@ -0,0 +1,49 @@
+//! Compares counting words with `bump` against the `Entry` API.
+//!
+//!     cargo bench --bench count
+
+use std::hint::black_box;
+use std::time::Instant;
+
+use rhh::HashMap;
+
+const ROUNDS: usize = 100;
+
+fn bench<F>(name: &str, words: &[String], mut count: F)
+where
+    F: FnMut(&mut HashMap<String, u64>, &str),
+{
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        let mut m = HashMap::new();
+        for w in words {
+            count(&mut m, black_box(w));
+        }
+        black_box(m);
+    }
+
+    let elapsed = start.elapsed();
+    println!(
+        "{:<6} {:>6.2} ns/word",
+        name,
+        elapsed.as_nanos() as f64 / (ROUNDS * words.len()) as f64
+    );
+}
+
+fn main() {
+    // 100k words drawn from a vocabulary of 5k.
+    let mut x = 1_u64;
+    let words: Vec<String> = (0..100_000)
+        .map(|_| {
+            x ^= x << 13;
+            x ^= x >> 7;
+            x ^= x << 17;
+            format!("word{}", x % 5000)
+        })
+        .collect();
+
+    bench("entry", &words, |m, w| {
+        *m.entry(w.to_string()).or_default() += 1
+    });
+    bench("bump", &words, |m, w| *m.bump(w.to_string()) += 1);
+}
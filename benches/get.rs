@@ -0,0 +1,40 @@
+//! Measures `get` on a lightly and a heavily loaded map. In the lightly loaded
+//! map nearly every hit sits in its home slot.
+//!
+//!     cargo bench --bench get
+
+use std::hint::black_box;
+use std::time::Instant;
+
+use rhh::HashMap;
+
+const LOOKUPS: usize = 10_000_000;
+
+fn bench(name: &str, capacity: usize, len: usize) {
+    let mut m = HashMap::with_capacity(capacity);
+    let keys: Vec<String> = (0..len).map(|i| format!("key-{}", i)).collect();
+    for (i, k) in keys.iter().enumerate() {
+        m.insert(k.clone(), i);
+    }
+
+    let start = Instant::now();
+    let mut sum = 0;
+    for i in 0..LOOKUPS {
+        sum += m.get(black_box(keys[i % len].as_str())).unwrap();
+    }
+    black_box(sum);
+
+    let elapsed = start.elapsed();
+    println!(
+        "{:<8} {:>8} elems in {:>8} slots: {:>6.2} ns/get",
+        name,
+        len,
+        m.capacity(),
+        elapsed.as_nanos() as f64 / LOOKUPS as f64
+    );
+}
+
+fn main() {
+    bench("sparse", 1 << 16, 1 << 12);
+    bench("dense", 1 << 16, (1 << 16) * 85 / 100);
+}
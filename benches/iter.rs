@@ -0,0 +1,42 @@
+//! Measures iterating a sparse map by scanning every slot with `iter` and by
+//! jumping between occupied slots with `iter_sparse`.
+//!
+//!     cargo bench --bench iter
+
+use std::hint::black_box;
+use std::time::Instant;
+
+use rhh::HashMap;
+
+const ROUNDS: usize = 100;
+
+fn bench<'a, I>(name: &str, m: &'a HashMap<String, usize>, iter: impl Fn() -> I)
+where
+    I: Iterator<Item = (&'a String, &'a usize)>,
+{
+    let start = Instant::now();
+    let mut sum = 0;
+    for _ in 0..ROUNDS {
+        sum += iter().map(|(_, v)| *v).sum::<usize>();
+    }
+    black_box(sum);
+
+    let elapsed = start.elapsed();
+    println!(
+        "{:<8} {:>8} elems in {:>8} slots: {:>8.2} us/iteration",
+        name,
+        m.len(),
+        m.capacity(),
+        elapsed.as_micros() as f64 / ROUNDS as f64
+    );
+}
+
+fn main() {
+    let mut m = HashMap::with_occupancy_bitmap(1 << 20);
+    for i in 0..1 << 10 {
+        m.insert(format!("key-{}", i), i);
+    }
+
+    bench("iter", &m, || m.iter());
+    bench("sparse", &m, || m.iter_sparse());
+}
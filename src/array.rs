@@ -0,0 +1,211 @@
+use std::borrow::Borrow;
+use std::fmt::{Debug, Formatter};
+use std::hash::{BuildHasher, Hash};
+
+use crate::{hash_key_with, slots, AsByte, HashElem, XxHash64Builder};
+
+/// ArrayHashMap is a hash map with a fixed number of N slots stored inline,
+/// so it never allocates or grows. N must be a power of 2. It suits small
+/// lookup tables, e.g. on the stack.
+pub struct ArrayHashMap<K, V, const N: usize, S = XxHash64Builder>
+where
+    K: Eq + Hash + AsByte,
+{
+    elems: [Option<HashElem<K, V>>; N],
+    len: usize,
+    hash_builder: S,
+}
+
+impl<K, V, const N: usize> ArrayHashMap<K, V, N>
+where
+    K: Eq + Hash + AsByte,
+{
+    /// new creates an empty map.
+    ///
+    /// Panics if N is not a power of 2 of at least 2.
+    pub fn new() -> Self {
+        Self::with_hasher(XxHash64Builder::default())
+    }
+}
+
+impl<K, V, const N: usize> Default for ArrayHashMap<K, V, N>
+where
+    K: Eq + Hash + AsByte,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, V, const N: usize, S> ArrayHashMap<K, V, N, S>
+where
+    K: Eq + Hash + AsByte,
+    S: BuildHasher,
+{
+    /// with_hasher is like `new`, but hashes keys with hash_builder.
+    ///
+    /// Panics if N is not a power of 2 of at least 2.
+    pub fn with_hasher(hash_builder: S) -> Self {
+        assert!(
+            N >= 2 && N.is_power_of_two(),
+            "N must be a power of 2 of at least 2, got {}",
+            N
+        );
+        Self {
+            elems: std::array::from_fn(|_| None),
+            len: 0,
+            hash_builder,
+        }
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        Q: ?Sized + Eq + Hash + AsByte,
+        K: Borrow<Q>,
+    {
+        let pos = self.index(key)?;
+        Some(&self.elems[pos].as_ref().unwrap().value)
+    }
+
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        Q: ?Sized + Eq + Hash + AsByte,
+        K: Borrow<Q>,
+    {
+        let pos = self.index(key)?;
+        Some(&mut self.elems[pos].as_mut().unwrap().value)
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        Q: ?Sized + Eq + Hash + AsByte,
+        K: Borrow<Q>,
+    {
+        self.index(key).is_some()
+    }
+
+    /// insert inserts val for key, and returns the previous value if the key
+    /// was already present. If the key is new and all slots are taken, the
+    /// pair is handed back as error.
+    pub fn insert(&mut self, key: K, val: V) -> Result<Option<V>, (K, V)> {
+        let hash = hash_key_with(&self.hash_builder, &key);
+        match slots::probe(&self.elems, hash, |k| *k == key) {
+            Ok(pos) => {
+                let e = self.elems[pos].as_mut().unwrap();
+                e.key = key;
+                Ok(Some(std::mem::replace(&mut e.value, val)))
+            }
+            Err(_) if self.len == N => Err((key, val)),
+            Err((pos, dist)) => {
+                slots::insert(
+                    &mut self.elems,
+                    pos,
+                    dist,
+                    HashElem::new(dist, key, val, hash),
+                );
+                self.len += 1;
+                Ok(None)
+            }
+        }
+    }
+
+    /// remove removes key from the map, and returns its value if it was
+    /// present.
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        Q: ?Sized + Eq + Hash + AsByte,
+        K: Borrow<Q>,
+    {
+        let pos = self.index(key)?;
+        self.len -= 1;
+        Some(slots::remove(&mut self.elems, pos, |_| {}).0.value)
+    }
+
+    /// iter returns the elements in slot order.
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        self.elems.iter().flatten().map(|e| (&e.key, &e.value))
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// capacity returns the number of slots, N.
+    pub fn capacity(&self) -> usize {
+        N
+    }
+
+    fn index<Q>(&self, key: &Q) -> Option<usize>
+    where
+        Q: ?Sized + Eq + Hash + AsByte,
+        K: Borrow<Q>,
+    {
+        let hash = hash_key_with(&self.hash_builder, key);
+        slots::probe(&self.elems, hash, |k| k.borrow() == key).ok()
+    }
+}
+
+impl<K, V, const N: usize, S> Debug for ArrayHashMap<K, V, N, S>
+where
+    K: Eq + Hash + AsByte + Debug,
+    V: Debug,
+    S: BuildHasher,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{hash_key, ArrayHashMap};
+
+    #[test]
+    fn test_array_hash_map() {
+        let mut m: ArrayHashMap<&str, u32, 16> = ArrayHashMap::new();
+        assert!(m.is_empty());
+        assert_eq!(16, m.capacity());
+
+        let keys: Vec<String> = (0..16).map(|i| format!("key-{}", i)).collect();
+        for (i, k) in keys.iter().enumerate() {
+            assert_eq!(Ok(None), m.insert(k, i as u32));
+        }
+        assert_eq!(16, m.len());
+        for (i, k) in keys.iter().enumerate() {
+            assert_eq!(Some(&(i as u32)), m.get(k.as_str()));
+        }
+
+        // A full map still takes overwrites, but hands back new keys.
+        assert_eq!(Ok(Some(3)), m.insert("key-3", 30));
+        assert_eq!(Err(("key-16", 16)), m.insert("key-16", 16));
+        assert!(!m.contains_key("key-16"));
+
+        assert_eq!(Some(30), m.remove("key-3"));
+        assert_eq!(None, m.remove("key-3"));
+        assert_eq!(Ok(None), m.insert("key-16", 16));
+        *m.get_mut("key-16").unwrap() += 1;
+        assert_eq!(Some(&17), m.get("key-16"));
+        assert_eq!(16, m.iter().count());
+        for (i, k) in keys.iter().enumerate().filter(|(i, _)| *i != 3) {
+            assert_eq!(Some(&(i as u32)), m.get(k.as_str()));
+        }
+
+        // Keys hash as in HashMap, so a lone key sits at its home slot.
+        let mut a: ArrayHashMap<&str, i32, 16> = ArrayHashMap::new();
+        a.insert("a", 1).unwrap();
+        assert_eq!(
+            Some((hash_key("a") & 15) as usize),
+            a.elems.iter().position(Option::is_some)
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "N must be a power of 2")]
+    fn test_array_hash_map_size() {
+        ArrayHashMap::<&str, u32, 12>::new();
+    }
+}
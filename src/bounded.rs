@@ -0,0 +1,202 @@
+use std::borrow::Borrow;
+use std::collections::VecDeque;
+use std::fmt::{Debug, Formatter};
+use std::hash::{BuildHasher, Hash};
+
+use crate::{AsByte, HashMap, XxHash64Builder};
+
+/// BoundedHashMap is a hash map holding at most `max_len` elements. Inserting
+/// a new key into a full map evicts the least recently inserted key. If
+/// `bump_on_get` is set, `get` also counts as a use, turning the eviction
+/// order into least recently used.
+pub struct BoundedHashMap<K, V, S = XxHash64Builder>
+where
+    K: Eq + Hash + AsByte,
+{
+    map: HashMap<K, V, S>,
+
+    // order holds the keys from least to most recently used.
+    order: VecDeque<K>,
+    max_len: usize,
+    bump_on_get: bool,
+}
+
+impl<K, V> BoundedHashMap<K, V>
+where
+    K: Eq + Hash + AsByte + Clone,
+{
+    /// new creates a map holding at most max_len elements.
+    ///
+    /// Panics if max_len is zero.
+    pub fn new(max_len: usize, bump_on_get: bool) -> Self {
+        Self::with_hasher(max_len, bump_on_get, XxHash64Builder::default())
+    }
+}
+
+impl<K, V, S> BoundedHashMap<K, V, S>
+where
+    K: Eq + Hash + AsByte + Clone,
+    S: BuildHasher,
+{
+    /// with_hasher is like `new`, but hashes keys with hash_builder.
+    ///
+    /// Panics if max_len is zero.
+    pub fn with_hasher(max_len: usize, bump_on_get: bool, hash_builder: S) -> Self {
+        assert!(max_len > 0, "max_len must be positive");
+        let mut map = HashMap::with_hasher(hash_builder);
+        map.reserve(max_len);
+        Self {
+            map,
+            order: VecDeque::with_capacity(max_len),
+            max_len,
+            bump_on_get,
+        }
+    }
+
+    /// get returns the value of key, marking it as recently used if
+    /// `bump_on_get` is set.
+    pub fn get<Q>(&mut self, key: &Q) -> Option<&V>
+    where
+        Q: ?Sized + Eq + Hash + AsByte,
+        K: Borrow<Q>,
+    {
+        if self.bump_on_get && self.map.index(key).is_some() {
+            self.bump(key);
+        }
+        self.map.get(key)
+    }
+
+    /// peek returns the value of key without changing the eviction order.
+    pub fn peek<Q>(&self, key: &Q) -> Option<&V>
+    where
+        Q: ?Sized + Eq + Hash + AsByte,
+        K: Borrow<Q>,
+    {
+        self.map.get(key)
+    }
+
+    /// insert inserts val for key and marks key as recently used. If this
+    /// makes the map exceed max_len, the least recently used element is
+    /// evicted. Returns the previous value of key.
+    pub fn insert(&mut self, key: K, val: V) -> Option<V> {
+        if self.map.index(&key).is_some() {
+            self.bump(&key);
+            return self.map.insert(key, val);
+        }
+
+        self.order.push_back(key.clone());
+        self.map.insert(key, val);
+        if self.order.len() > self.max_len {
+            let oldest = self.order.pop_front().unwrap();
+            self.map.remove(&oldest);
+        }
+        None
+    }
+
+    /// remove removes key from the map, and returns its value if it was
+    /// present.
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        Q: ?Sized + Eq + Hash + AsByte,
+        K: Borrow<Q>,
+    {
+        let val = self.map.remove(key)?;
+        let i = self.position(key);
+        self.order.remove(i);
+        Some(val)
+    }
+
+    pub fn len(&self) -> u64 {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    pub fn max_len(&self) -> usize {
+        self.max_len
+    }
+
+    /// bump moves the present key to the back of the eviction order.
+    fn bump<Q>(&mut self, key: &Q)
+    where
+        Q: ?Sized + Eq + Hash + AsByte,
+        K: Borrow<Q>,
+    {
+        let i = self.position(key);
+        let k = self.order.remove(i).unwrap();
+        self.order.push_back(k);
+    }
+
+    /// position returns the position of the present key in the eviction order.
+    fn position<Q>(&self, key: &Q) -> usize
+    where
+        Q: ?Sized + Eq + Hash + AsByte,
+        K: Borrow<Q>,
+    {
+        self.order.iter().position(|k| k.borrow() == key).unwrap()
+    }
+}
+
+impl<K, V, S> Debug for BoundedHashMap<K, V, S>
+where
+    K: Eq + Hash + AsByte + Debug,
+    V: Debug,
+    S: BuildHasher,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        self.map.fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::BoundedHashMap;
+
+    #[test]
+    fn test_bounded_evicts_oldest() {
+        let mut m = BoundedHashMap::new(3, false);
+        for i in 0..3 {
+            m.insert(i.to_string(), i);
+        }
+        assert_eq!(3, m.len());
+
+        // Reads don't count as use.
+        assert_eq!(Some(&0), m.get("0"));
+
+        m.insert("3".to_string(), 3);
+        assert_eq!(3, m.len());
+        assert_eq!(None, m.get("0"));
+        for i in 1..4 {
+            assert_eq!(Some(&i), m.get(&i.to_string()));
+        }
+
+        // Overwriting counts as use, so "2" is evicted next.
+        assert_eq!(Some(1), m.insert("1".to_string(), 10));
+        m.insert("4".to_string(), 4);
+        assert_eq!(None, m.peek("2"));
+        assert_eq!(Some(&10), m.peek("1"));
+    }
+
+    #[test]
+    fn test_bounded_bump_on_get() {
+        let mut m = BoundedHashMap::new(3, true);
+        for i in 0..3 {
+            m.insert(i.to_string(), i);
+        }
+
+        // "0" was used last, so "1" is the least recently used.
+        assert_eq!(Some(&0), m.get("0"));
+        m.insert("3".to_string(), 3);
+        assert_eq!(None, m.peek("1"));
+        assert_eq!(Some(&0), m.peek("0"));
+
+        assert_eq!(Some(3), m.remove("3"));
+        assert_eq!(2, m.len());
+        m.insert("4".to_string(), 4);
+        m.insert("5".to_string(), 5);
+        assert_eq!(None, m.peek("2"));
+        assert_eq!(3, m.len());
+    }
+}
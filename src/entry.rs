@@ -0,0 +1,702 @@
+use std::fmt::{Debug, Formatter};
+use std::hash::{BuildHasher, Hash};
+
+use crate::{AsByte, HashMap};
+
+/// Entry is a view into a single slot of the map, which is either occupied or
+/// vacant. It is constructed by `HashMap::entry`.
+pub enum Entry<'a, K, V, S>
+where
+    K: Eq + Hash + AsByte,
+{
+    Occupied(OccupiedEntry<'a, K, V, S>),
+    Vacant(VacantEntry<'a, K, V, S>),
+}
+
+impl<'a, K, V, S> Entry<'a, K, V, S>
+where
+    K: Eq + Hash + AsByte,
+    S: BuildHasher,
+{
+    /// or_insert inserts default if the entry is vacant, and returns a mutable
+    /// reference to the value in the entry. Any grow has already happened in
+    /// `HashMap::entry`, so the reference points into the current array.
+    pub fn or_insert(self, default: V) -> &'a mut V {
+        match self {
+            Entry::Occupied(e) => e.into_mut(),
+            Entry::Vacant(e) => e.insert(default),
+        }
+    }
+
+    /// or_insert_with inserts the result of default if the entry is vacant, and
+    /// returns a mutable reference to the value in the entry.
+    pub fn or_insert_with<F>(self, default: F) -> &'a mut V
+    where
+        F: FnOnce() -> V,
+    {
+        match self {
+            Entry::Occupied(e) => e.into_mut(),
+            Entry::Vacant(e) => e.insert(default()),
+        }
+    }
+
+    /// or_insert_with_key is like `or_insert_with`, but default is given the
+    /// key of the entry.
+    pub fn or_insert_with_key<F>(self, default: F) -> &'a mut V
+    where
+        F: FnOnce(&K) -> V,
+    {
+        match self {
+            Entry::Occupied(e) => e.into_mut(),
+            Entry::Vacant(e) => {
+                let value = default(e.key());
+                e.insert(value)
+            }
+        }
+    }
+
+    /// or_try_insert_with inserts the result of default if the entry is vacant,
+    /// and returns a mutable reference to the value in the entry. If default
+    /// fails, nothing is inserted and the error is returned.
+    pub fn or_try_insert_with<F, E>(self, default: F) -> Result<&'a mut V, E>
+    where
+        F: FnOnce() -> Result<V, E>,
+    {
+        match self {
+            Entry::Occupied(e) => Ok(e.into_mut()),
+            Entry::Vacant(e) => Ok(e.insert(default()?)),
+        }
+    }
+
+    /// and_modify calls f with the value of an occupied entry before any
+    /// potential insert.
+    pub fn and_modify<F>(self, f: F) -> Self
+    where
+        F: FnOnce(&mut V),
+    {
+        match self {
+            Entry::Occupied(mut e) => {
+                f(e.get_mut());
+                Entry::Occupied(e)
+            }
+            Entry::Vacant(e) => Entry::Vacant(e),
+        }
+    }
+
+    /// and_replace overwrites the value of an occupied entry with value. A
+    /// vacant entry is returned unchanged, so a following `or_insert` can
+    /// insert a default.
+    pub fn and_replace(self, value: V) -> Self {
+        match self {
+            Entry::Occupied(mut e) => {
+                e.insert(value);
+                Entry::Occupied(e)
+            }
+            Entry::Vacant(e) => Entry::Vacant(e),
+        }
+    }
+
+    /// insert_entry sets the value of the entry, inserting it if the entry is
+    /// vacant, and returns the now occupied entry.
+    pub fn insert_entry(self, value: V) -> OccupiedEntry<'a, K, V, S> {
+        match self {
+            Entry::Occupied(mut e) => {
+                e.insert(value);
+                e
+            }
+            Entry::Vacant(e) => e.insert_entry(value),
+        }
+    }
+
+    /// key returns the key of the entry.
+    pub fn key(&self) -> &K {
+        match self {
+            Entry::Occupied(e) => e.key(),
+            Entry::Vacant(e) => e.key(),
+        }
+    }
+
+    /// is_occupied reports whether the key of the entry is present, e.g. to
+    /// tell whether a preceding `and_modify` ran.
+    pub fn is_occupied(&self) -> bool {
+        matches!(self, Entry::Occupied(_))
+    }
+
+    pub fn is_vacant(&self) -> bool {
+        matches!(self, Entry::Vacant(_))
+    }
+}
+
+impl<'a, K, V, S> Entry<'a, K, V, S>
+where
+    K: Eq + Hash + AsByte,
+    V: Default,
+    S: BuildHasher,
+{
+    /// or_default inserts the default value if the entry is vacant, and returns
+    /// a mutable reference to the value in the entry.
+    pub fn or_default(self) -> &'a mut V {
+        self.or_insert_with(V::default)
+    }
+}
+
+impl<'a, K, T, S> Entry<'a, K, Vec<T>, S>
+where
+    K: Eq + Hash + AsByte,
+    S: BuildHasher,
+{
+    /// or_default_with_capacity inserts an empty `Vec` with room for capacity
+    /// items if the entry is vacant, and returns a mutable reference to the
+    /// value in the entry. Useful for multimaps with known fan-out.
+    pub fn or_default_with_capacity(self, capacity: usize) -> &'a mut Vec<T> {
+        self.or_insert_with(|| Vec::with_capacity(capacity))
+    }
+}
+
+impl<K, V, S> Debug for Entry<'_, K, V, S>
+where
+    K: Eq + Hash + AsByte + Debug,
+    V: Debug,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Entry::Occupied(e) => f.debug_tuple("Entry").field(e).finish(),
+            Entry::Vacant(e) => f.debug_tuple("Entry").field(e).finish(),
+        }
+    }
+}
+
+impl<K, V, S> HashMap<K, V, S>
+where
+    K: Eq + Hash + AsByte,
+    S: BuildHasher,
+{
+    /// modify_or_insert_with calls modify on the value of key if it is present,
+    /// and otherwise inserts the value returned by insert. The key is hashed
+    /// once.
+    pub fn modify_or_insert_with<FM, FI>(&mut self, key: K, modify: FM, insert: FI)
+    where
+        FM: FnOnce(&mut V),
+        FI: FnOnce() -> V,
+    {
+        match self.entry(key) {
+            Entry::Occupied(e) => modify(e.into_mut()),
+            Entry::Vacant(e) => {
+                e.insert(insert());
+            }
+        }
+    }
+
+    /// entries_with calls f with the entry of each key in turn, for batch
+    /// upserts of a known set of keys. Room for all keys is reserved up front,
+    /// so the batch grows the map at most once. The entries can't be handed
+    /// out at the same time: each borrows the map mutably, and inserting into
+    /// a vacant entry shifts elems, which would invalidate the slots
+    /// remembered by the others.
+    pub fn entries_with<const N: usize, F>(&mut self, keys: [K; N], mut f: F)
+    where
+        F: FnMut(Entry<'_, K, V, S>),
+    {
+        self.reserve(N);
+        for key in keys {
+            f(self.entry(key));
+        }
+    }
+
+    /// get_or_insert_default returns a mutable reference to the value of key,
+    /// inserting the default value first if key is missing. It is shorthand
+    /// for `entry(key).or_default()`.
+    pub fn get_or_insert_default(&mut self, key: K) -> &mut V
+    where
+        V: Default,
+    {
+        self.entry(key).or_default()
+    }
+
+    /// bump is `get_or_insert_default` for hot counting loops such as
+    /// `*m.bump(word) += 1`. It probes once and goes straight to the slot
+    /// without building an `Entry`, which lets the compiler inline it into
+    /// the loop.
+    #[inline]
+    pub fn bump(&mut self, key: K) -> &mut V
+    where
+        V: Default,
+    {
+        if self.len > self.threshold {
+            self.grow();
+        }
+
+        let hash = self.hash(&key);
+        let index = match self.probe(hash, &key) {
+            Ok(index) => index,
+            Err((mut pos, mut dist)) => {
+                while self.probe_too_long(pos, dist) {
+                    self.grow();
+                    (pos, dist) = self.probe(hash, &key).unwrap_err();
+                }
+                self.insert_at(pos, dist, hash, key, V::default())
+            }
+        };
+        &mut self.elems[index].as_mut().unwrap().value
+    }
+}
+
+impl<V, S> HashMap<Vec<u8>, V, S>
+where
+    S: BuildHasher,
+{
+    /// entry_bytes is like `entry`, but takes the key as a byte slice. The
+    /// owned `Vec<u8>` key is only allocated if the entry is vacant.
+    pub fn entry_bytes(&mut self, key: &[u8]) -> Entry<'_, Vec<u8>, V, S> {
+        // Grow up front so a vacant entry's insertion point stays valid.
+        if self.len > self.threshold {
+            self.grow();
+        }
+
+        let hash = self.hash(key);
+        match self.probe(hash, key) {
+            Ok(index) => Entry::Occupied(OccupiedEntry::new(self, index)),
+            Err((pos, dist)) => {
+                Entry::Vacant(VacantEntry::new(self, hash, key.to_vec(), pos, dist))
+            }
+        }
+    }
+}
+
+/// OccupiedEntry is a view into an occupied slot, it remembers the position
+/// of the key so no further probing is needed.
+pub struct OccupiedEntry<'a, K, V, S>
+where
+    K: Eq + Hash + AsByte,
+{
+    map: &'a mut HashMap<K, V, S>,
+    index: usize,
+}
+
+impl<'a, K, V, S> OccupiedEntry<'a, K, V, S>
+where
+    K: Eq + Hash + AsByte,
+{
+    pub(crate) fn new(map: &'a mut HashMap<K, V, S>, index: usize) -> Self {
+        Self { map, index }
+    }
+
+    pub fn key(&self) -> &K {
+        &self.map.elems[self.index].as_ref().unwrap().key
+    }
+
+    pub fn get(&self) -> &V {
+        &self.map.elems[self.index].as_ref().unwrap().value
+    }
+
+    pub fn get_mut(&mut self) -> &mut V {
+        &mut self.map.elems[self.index].as_mut().unwrap().value
+    }
+
+    /// into_mut converts the entry into a mutable reference to its value with
+    /// the lifetime of the map.
+    pub fn into_mut(self) -> &'a mut V {
+        &mut self.map.elems[self.index].as_mut().unwrap().value
+    }
+
+    /// insert sets the value of the entry, and returns the old value.
+    pub fn insert(&mut self, value: V) -> V {
+        std::mem::replace(self.get_mut(), value)
+    }
+
+    /// replace_key stores key in place of the entry's key, and returns the
+    /// old key. The keys must be equal, e.g. when interning a canonical key
+    /// instance; debug builds check that they hash and compare equal.
+    pub fn replace_key(&mut self, key: K) -> K
+    where
+        S: BuildHasher,
+    {
+        let e = self.map.elems[self.index].as_ref().unwrap();
+        debug_assert!(
+            self.map.hash(&key) == e.hash && self.map.key_eq(&key, &e.key),
+            "replacement key differs from the entry's key"
+        );
+        let e = self.map.elems[self.index].as_mut().unwrap();
+        std::mem::replace(&mut e.key, key)
+    }
+}
+
+impl<K, V, S> Debug for OccupiedEntry<'_, K, V, S>
+where
+    K: Eq + Hash + AsByte + Debug,
+    V: Debug,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let e = self.map.elems[self.index].as_ref().unwrap();
+        f.debug_struct("OccupiedEntry")
+            .field("key", &e.key)
+            .field("value", &e.value)
+            .finish()
+    }
+}
+
+/// VacantEntry is a view into a vacant slot, it remembers the hash of the key
+/// and the insertion point found while probing.
+pub struct VacantEntry<'a, K, V, S>
+where
+    K: Eq + Hash + AsByte,
+{
+    map: &'a mut HashMap<K, V, S>,
+    hash: u64,
+    key: K,
+    pos: usize,
+    dist: u64,
+}
+
+impl<'a, K, V, S> VacantEntry<'a, K, V, S>
+where
+    K: Eq + Hash + AsByte,
+    S: BuildHasher,
+{
+    pub(crate) fn new(
+        map: &'a mut HashMap<K, V, S>,
+        hash: u64,
+        key: K,
+        pos: usize,
+        dist: u64,
+    ) -> Self {
+        Self {
+            map,
+            hash,
+            key,
+            pos,
+            dist,
+        }
+    }
+
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    pub fn into_key(self) -> K {
+        self.key
+    }
+
+    /// insert sets the value of the entry at the remembered insertion point,
+    /// and returns a mutable reference to it.
+    pub fn insert(self, value: V) -> &'a mut V {
+        self.insert_entry(value).into_mut()
+    }
+
+    /// insert_entry is like `insert`, but returns the occupied entry.
+    pub fn insert_entry(self, value: V) -> OccupiedEntry<'a, K, V, S> {
+        let (mut pos, mut dist) = (self.pos, self.dist);
+        while self.map.probe_too_long(pos, dist) {
+            self.map.grow();
+            (pos, dist) = self.map.probe(self.hash, &self.key).unwrap_err();
+        }
+        let index = self.map.insert_at(pos, dist, self.hash, self.key, value);
+        OccupiedEntry::new(self.map, index)
+    }
+}
+
+impl<K, V, S> Debug for VacantEntry<'_, K, V, S>
+where
+    K: Eq + Hash + AsByte + Debug,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("VacantEntry").field(&self.key).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::hash::BuildHasher;
+    use std::rc::Rc;
+
+    use crate::{Entry, HashMap};
+
+    /// CountingBuilder counts how many hashers it has built, i.e. how many
+    /// times a key was hashed.
+    #[derive(Clone, Default)]
+    struct CountingBuilder {
+        count: Rc<Cell<usize>>,
+    }
+
+    impl BuildHasher for CountingBuilder {
+        type Hasher = twox_hash::XxHash64;
+
+        fn build_hasher(&self) -> Self::Hasher {
+            self.count.set(self.count.get() + 1);
+            twox_hash::XxHash64::with_seed(0)
+        }
+    }
+
+    #[test]
+    fn test_entry_hashes_once() {
+        let builder = CountingBuilder::default();
+        let count = builder.count.clone();
+        let mut m = HashMap::with_hasher(builder);
+
+        *m.entry("a".to_string())
+            .and_modify(|v| *v += 1)
+            .or_insert(1) += 10;
+        assert_eq!(1, count.get());
+
+        m.entry("a".to_string())
+            .and_modify(|v| *v += 1)
+            .or_insert(1);
+        assert_eq!(2, count.get());
+
+        assert_eq!(Some(&12), m.get("a"));
+        assert_eq!(1, m.len());
+    }
+
+    #[test]
+    fn test_entry_is_occupied() {
+        let mut m = HashMap::new();
+        let e = m.entry("a".to_string());
+        assert!(e.is_vacant());
+        assert!(!e.is_occupied());
+        e.or_insert(1);
+
+        let mut ran = false;
+        let e = m.entry("a".to_string()).and_modify(|v| {
+            ran = true;
+            *v += 1
+        });
+        assert!(e.is_occupied());
+        assert!(!e.is_vacant());
+        assert!(ran);
+        assert_eq!(Some(&2), m.get("a"));
+    }
+
+    #[test]
+    fn test_modify_or_insert_with() {
+        let builder = CountingBuilder::default();
+        let count = builder.count.clone();
+        let mut m = HashMap::with_hasher(builder);
+
+        m.modify_or_insert_with("a".to_string(), |_| unreachable!(), || 1);
+        assert_eq!(1, count.get());
+        assert_eq!(Some(&1), m.get("a"));
+        assert_eq!(2, count.get());
+
+        m.modify_or_insert_with("a".to_string(), |v| *v += 10, || unreachable!());
+        assert_eq!(3, count.get());
+        assert_eq!(Some(&11), m.get("a"));
+        assert_eq!(1, m.len());
+    }
+
+    #[test]
+    fn test_entry_insert_entry() {
+        let mut m = HashMap::new();
+
+        let mut e = m.entry("a".to_string()).insert_entry(1);
+        assert_eq!(&1, e.get());
+        *e.get_mut() += 1;
+        assert_eq!("a", e.key());
+
+        let e = m.entry("a".to_string()).insert_entry(10);
+        assert_eq!(&10, e.get());
+        assert_eq!(Some(&10), m.get("a"));
+        assert_eq!(1, m.len());
+    }
+
+    #[test]
+    fn test_get_or_insert_default() {
+        let mut m: HashMap<String, Vec<u32>> = HashMap::new();
+
+        let v = m.get_or_insert_default("a".to_string());
+        assert!(v.is_empty());
+        v.push(1);
+        m.get_or_insert_default("a".to_string()).push(2);
+
+        assert_eq!(Some(&vec![1, 2]), m.get("a"));
+        assert_eq!(1, m.len());
+    }
+
+    #[test]
+    fn test_bump() {
+        let builder = CountingBuilder::default();
+        let count = builder.count.clone();
+        let mut m = HashMap::with_capacity_and_hasher(16, builder);
+
+        let words = "a b a c b a d e f g h i j k l m n o p q r s a";
+        for (i, w) in words.split(' ').enumerate() {
+            *m.bump(w.to_string()) += 1;
+            // One hash per call, including the calls that grow the map
+            // since the stored hashes are reused.
+            assert_eq!(i + 1, count.get());
+        }
+        assert!(m.capacity() > 16);
+        assert_eq!(Some(&4), m.get("a"));
+        assert_eq!(Some(&2), m.get("b"));
+        assert_eq!(Some(&1), m.get("s"));
+    }
+
+    #[test]
+    fn test_entry_replace_key() {
+        let mut m = HashMap::new();
+        m.insert("key".to_string(), 1);
+
+        let canonical = String::from("key");
+        let ptr = canonical.as_ptr();
+        let old = match m.entry("key".to_string()) {
+            Entry::Occupied(mut e) => e.replace_key(canonical),
+            Entry::Vacant(_) => unreachable!(),
+        };
+        assert_eq!("key", old);
+        assert_ne!(ptr, old.as_ptr());
+        assert_eq!(ptr, m.keys().next().unwrap().as_ptr());
+        assert_eq!(Some(&1), m.get("key"));
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "replacement key differs")]
+    fn test_entry_replace_key_differs() {
+        let mut m = HashMap::new();
+        m.insert("a".to_string(), 1);
+        if let Entry::Occupied(mut e) = m.entry("a".to_string()) {
+            e.replace_key("b".to_string());
+        }
+    }
+
+    #[test]
+    fn test_entries_with() {
+        let mut m = HashMap::with_capacity(4);
+        m.insert("b".to_string(), 10);
+
+        let keys = ["a".to_string(), "b".to_string(), "c".to_string()];
+        m.entries_with(keys, |e| {
+            *e.and_modify(|v| *v += 1).or_insert(1) *= 2;
+        });
+        assert_eq!(Some(&2), m.get("a"));
+        assert_eq!(Some(&22), m.get("b"));
+        assert_eq!(Some(&2), m.get("c"));
+        assert_eq!(3, m.len());
+    }
+
+    #[test]
+    fn test_entry_or_try_insert_with() {
+        let mut m = HashMap::new();
+
+        let v = m
+            .entry("a".to_string())
+            .or_try_insert_with(|| "1".parse::<u32>());
+        assert_eq!(Ok(&mut 1), v);
+
+        let v = m
+            .entry("b".to_string())
+            .or_try_insert_with(|| "x".parse::<u32>());
+        assert!(v.is_err());
+        assert_eq!(None, m.get("b"));
+        assert_eq!(1, m.len());
+
+        // An occupied entry never calls the closure.
+        let v = m
+            .entry("a".to_string())
+            .or_try_insert_with(|| "x".parse::<u32>());
+        assert_eq!(Ok(&mut 1), v);
+    }
+
+    #[test]
+    fn test_entry_and_replace() {
+        let mut m = HashMap::new();
+        m.insert("a".to_string(), 1);
+
+        let v = m.entry("a".to_string()).and_replace(10).or_insert(0);
+        assert_eq!(10, *v);
+
+        let v = m.entry("b".to_string()).and_replace(10).or_insert(0);
+        assert_eq!(0, *v);
+
+        assert_eq!(Some(&10), m.get("a"));
+        assert_eq!(Some(&0), m.get("b"));
+    }
+
+    #[test]
+    fn test_entry_multimap() {
+        let mut m: HashMap<String, Vec<u32>> = HashMap::new();
+        for i in 0..100 {
+            m.entry((i % 10).to_string()).or_default().push(i);
+        }
+        for i in 0..10 {
+            m.entry(i.to_string())
+                .or_default_with_capacity(16)
+                .push(100 + i);
+        }
+        m.entry("10".to_string())
+            .or_default_with_capacity(16)
+            .push(110);
+
+        assert_eq!(11, m.len());
+        for i in 0..10 {
+            let expected: Vec<u32> = (0..10).map(|j| j * 10 + i).chain([100 + i]).collect();
+            assert_eq!(Some(&expected), m.get(&i.to_string()));
+        }
+        let v = m.get("10").unwrap();
+        assert_eq!(&[110], v.as_slice());
+        assert!(v.capacity() >= 16);
+    }
+
+    #[test]
+    fn test_entry_or_insert_grows() {
+        let mut m = HashMap::with_capacity(16);
+        for i in 0..15 {
+            m.insert(i.to_string(), i);
+        }
+        assert_eq!(16, m.capacity());
+
+        // The map is past its threshold of 14, so the entry grows it first.
+        let v = m.entry("15".to_string()).or_insert(0);
+        *v = 42;
+        assert_eq!(32, m.capacity());
+        assert_eq!(Some(&42), m.get("15"));
+
+        // get_mut never grows.
+        *m.get_mut("15").unwrap() += 1;
+        assert_eq!(32, m.capacity());
+        assert_eq!(Some(&43), m.get("15"));
+    }
+
+    #[test]
+    fn test_entry_or_insert_with_grows() {
+        let mut m = HashMap::with_capacity(16);
+        for i in 0..14 {
+            m.insert(i.to_string(), i);
+        }
+
+        // At the threshold of 14 elems there's still room for one more.
+        m.entry("14".to_string()).or_insert_with(|| 14);
+        assert_eq!(16, m.capacity());
+
+        // Past it, the entry grows the map before the closure's value is
+        // placed, so the reference points into the grown array.
+        let v = m.entry("15".to_string()).or_insert_with(|| 0);
+        *v += 42;
+        let ptr = v as *const i32;
+        assert_eq!(32, m.capacity());
+        assert_eq!(Some(ptr), m.get("15").map(|v| v as *const i32));
+        assert_eq!(Some(&42), m.get("15"));
+        assert_eq!(16, m.len());
+        for i in 0..15 {
+            assert_eq!(Some(&i), m.get(&i.to_string()));
+        }
+    }
+
+    #[test]
+    fn test_entry() {
+        let mut m = HashMap::new();
+
+        let size = 512;
+        for i in 0..size {
+            *m.entry(i.to_string()).or_insert(0) += i;
+        }
+        for i in 0..size {
+            *m.entry(i.to_string()).or_default() += i;
+        }
+        assert_eq!(size as u64, m.len());
+
+        for i in 0..size {
+            assert_eq!(Some(&(i * 2)), m.get(&i.to_string()));
+        }
+    }
+}
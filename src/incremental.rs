@@ -0,0 +1,334 @@
+use std::borrow::Borrow;
+use std::fmt::{Debug, Formatter};
+use std::hash::{BuildHasher, Hash};
+use std::mem;
+
+use crate::{AsByte, HashMap, XxHash64Builder};
+
+/// DEFAULT_STEP is the number of elems migrated per operation by default.
+const DEFAULT_STEP: usize = 32;
+
+/// IncrementalHashMap is a hash map that spreads the cost of growing over
+/// subsequent operations. When the map runs out of slots, it allocates a new
+/// array twice as large but keeps the old one live, and every `insert`,
+/// `get`, `get_mut` and `remove` moves up to `step` elems from the old array
+/// to the new one. Lookups consult both arrays until the migration is done.
+/// If the new array fills up before that, it is queued behind the old one
+/// and the migration carries on step by step. This caps the worst-case
+/// latency of a single operation at the cost of extra probes during
+/// migrations.
+pub struct IncrementalHashMap<K, V, S = XxHash64Builder>
+where
+    K: Eq + Hash + AsByte,
+{
+    map: HashMap<K, V, S>,
+
+    // old holds the arrays being migrated into map, oldest first.
+    old: Vec<HashMap<K, V, S>>,
+    // cursor is the next slot of the oldest array to migrate.
+    cursor: usize,
+    step: usize,
+}
+
+impl<K, V> IncrementalHashMap<K, V>
+where
+    K: Eq + Hash + AsByte,
+{
+    pub fn new() -> Self {
+        Self::from_map(HashMap::new(), DEFAULT_STEP)
+    }
+
+    pub fn with_capacity_and_step(capacity: usize, step: usize) -> Self {
+        Self::from_map(HashMap::with_capacity(capacity), step)
+    }
+}
+
+impl<K, V> Default for IncrementalHashMap<K, V>
+where
+    K: Eq + Hash + AsByte,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, V, S> IncrementalHashMap<K, V, S>
+where
+    K: Eq + Hash + AsByte,
+    S: BuildHasher + Clone,
+{
+    /// from_map wraps map, migrating step elems per operation when growing.
+    ///
+    /// Panics if step is zero.
+    pub fn from_map(map: HashMap<K, V, S>, step: usize) -> Self {
+        assert!(step > 0, "step must be positive");
+        Self {
+            map,
+            old: Vec::new(),
+            cursor: 0,
+            step,
+        }
+    }
+
+    /// get returns the value of key. Like every other operation it moves the
+    /// migration forward, which is why it takes `&mut self`.
+    pub fn get<Q>(&mut self, key: &Q) -> Option<&V>
+    where
+        Q: ?Sized + Eq + Hash + AsByte,
+        K: Borrow<Q>,
+    {
+        self.get_mut(key).map(|v| &*v)
+    }
+
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        Q: ?Sized + Eq + Hash + AsByte,
+        K: Borrow<Q>,
+    {
+        self.migrate(self.step);
+
+        let hash = self.map.hash(key);
+        if let Ok(pos) = self.map.probe(hash, key) {
+            return Some(&mut self.map.elems[pos].as_mut().unwrap().value);
+        }
+
+        self.old.iter_mut().find_map(|old| {
+            let pos = old.probe(hash, key).ok()?;
+            Some(&mut old.elems[pos].as_mut().unwrap().value)
+        })
+    }
+
+    /// insert inserts val for key, and returns the previous value if the key
+    /// was already present.
+    pub fn insert(&mut self, key: K, val: V) -> Option<V> {
+        self.migrate(self.step);
+
+        // A key still in an old array moves to the new one.
+        let hash = self.map.hash(&key);
+        let prev = self.old.iter_mut().find_map(|old| {
+            let pos = old.probe(hash, &key).ok()?;
+            Some(old.remove_at(pos).value)
+        });
+
+        // Start growing if we've run out of slots.
+        if self.map.len > self.map.threshold {
+            self.start_grow();
+        }
+
+        self.map.insert_raw(hash, key, val).or(prev)
+    }
+
+    /// remove removes key from the map, and returns its value if it was
+    /// present.
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        Q: ?Sized + Eq + Hash + AsByte,
+        K: Borrow<Q>,
+    {
+        self.migrate(self.step);
+
+        let hash = self.map.hash(key);
+        if let Ok(pos) = self.map.probe(hash, key) {
+            return Some(self.map.remove_at(pos).value);
+        }
+
+        self.old.iter_mut().find_map(|old| {
+            let pos = old.probe(hash, key).ok()?;
+            Some(old.remove_at(pos).value)
+        })
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        self.map
+            .iter()
+            .chain(self.old.iter().flat_map(|m| m.iter()))
+    }
+
+    pub fn len(&self) -> u64 {
+        self.map.len + self.old.iter().map(|m| m.len).sum::<u64>()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// capacity returns the capacity of the newest array.
+    pub fn capacity(&self) -> u64 {
+        self.map.capacity
+    }
+
+    /// is_migrating reports whether elems are still being moved out of an old
+    /// array.
+    pub fn is_migrating(&self) -> bool {
+        !self.old.is_empty()
+    }
+
+    /// finish_migration moves all remaining elems out of the old arrays.
+    pub fn finish_migration(&mut self) {
+        self.migrate(usize::MAX);
+    }
+
+    /// into_inner finishes any migration and returns the underlying map.
+    pub fn into_inner(mut self) -> HashMap<K, V, S> {
+        self.finish_migration();
+        self.map
+    }
+
+    /// start_grow allocates a new array twice as large and starts migrating
+    /// into it. The current array is queued behind any array still being
+    /// migrated, so the migration in progress just carries on step by step.
+    fn start_grow(&mut self) {
+        let new_map = self.map.empty_like(self.map.capacity * 2);
+        self.old.push(mem::replace(&mut self.map, new_map));
+    }
+
+    /// migrate moves up to n elems from the old arrays to the new one, oldest
+    /// array first.
+    fn migrate(&mut self, n: usize) {
+        let mut n = n;
+        while n > 0 {
+            let old = match self.old.first_mut() {
+                Some(old) => old,
+                None => return,
+            };
+            if old.len == 0 {
+                self.old.remove(0);
+                self.cursor = 0;
+                continue;
+            }
+
+            // Removing shifts the next displaced elem into the cursor's slot,
+            // so only advance past empty slots.
+            if old.elems[self.cursor].is_some() {
+                let e = old.remove_at(self.cursor);
+
+                // Migrated elems count against the new array's threshold too.
+                if self.map.len > self.map.threshold {
+                    self.start_grow();
+                }
+                self.map.insert_raw(e.hash, e.key, e.value);
+                n -= 1;
+            } else {
+                self.cursor = (self.cursor + 1) & old.mask;
+            }
+        }
+
+        // Drop an array emptied by the last step right away.
+        if self.old.first().is_some_and(|old| old.len == 0) {
+            self.old.remove(0);
+            self.cursor = 0;
+        }
+    }
+}
+
+impl<K, V, S> Debug for IncrementalHashMap<K, V, S>
+where
+    K: Eq + Hash + AsByte + Debug,
+    V: Debug,
+    S: BuildHasher + Clone,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::IncrementalHashMap;
+
+    #[test]
+    fn test_incremental_insert_get() {
+        let mut m = IncrementalHashMap::with_capacity_and_step(16, 2);
+
+        let mut migrations = 0;
+        for i in 0..2000 {
+            m.insert(i.to_string(), i);
+            if m.is_migrating() {
+                migrations += 1;
+            }
+
+            // Every key stays reachable, whichever array it is in.
+            for j in (0..=i).step_by(7) {
+                assert_eq!(Some(&j), m.get(&j.to_string()));
+            }
+        }
+        assert!(migrations > 0);
+        assert_eq!(2000, m.len());
+        assert_eq!(2000, m.iter().count());
+
+        let m = m.into_inner();
+        assert_eq!(2000, m.len());
+        for i in 0..2000 {
+            assert_eq!(Some(&i), m.get(&i.to_string()));
+        }
+    }
+
+    #[test]
+    fn test_incremental_get_migrates() {
+        let mut m = IncrementalHashMap::with_capacity_and_step(16, 2);
+        for i in 0..16 {
+            m.insert(i.to_string(), i);
+        }
+        assert!(m.is_migrating());
+
+        // Reads alone drive the migration to its end.
+        for _ in 0..8 {
+            assert_eq!(Some(&3), m.get("3"));
+        }
+        assert!(!m.is_migrating());
+    }
+
+    #[test]
+    fn test_incremental_grow_while_migrating() {
+        let mut m = IncrementalHashMap::with_capacity_and_step(16, 1);
+        for i in 0..16 {
+            m.insert(i.to_string(), i);
+        }
+        assert_eq!(1, m.old.len());
+
+        // Growing again queues the current array behind the old one instead
+        // of finishing the migration in one go.
+        m.start_grow();
+        assert_eq!(2, m.old.len());
+        assert!(m.old[0].len > 0);
+
+        for i in 16..500 {
+            m.insert(i.to_string(), i);
+            for j in (0..=i).step_by(11) {
+                assert_eq!(Some(&j), m.get(&j.to_string()));
+            }
+        }
+        assert_eq!(500, m.len());
+
+        let m = m.into_inner();
+        for i in 0..500 {
+            assert_eq!(Some(&i), m.get(&i.to_string()));
+        }
+    }
+
+    #[test]
+    fn test_incremental_update_remove() {
+        let mut m = IncrementalHashMap::with_capacity_and_step(16, 1);
+        for i in 0..500 {
+            m.insert(i.to_string(), i);
+        }
+        assert!(m.is_migrating());
+
+        // Interleave writes with the migration.
+        for i in 0..500 {
+            if i % 2 == 0 {
+                assert_eq!(Some(i), m.insert(i.to_string(), i * 10));
+                *m.get_mut(&i.to_string()).unwrap() += 1;
+            } else {
+                assert_eq!(Some(i), m.remove(&i.to_string()));
+                assert_eq!(None, m.get_mut(&i.to_string()));
+            }
+        }
+
+        assert_eq!(250, m.len());
+        for i in 0..500 {
+            let expected = if i % 2 == 0 { Some(i * 10 + 1) } else { None };
+            assert_eq!(expected.as_ref(), m.get(&i.to_string()));
+        }
+    }
+}
@@ -0,0 +1,129 @@
+use std::hash::BuildHasher;
+use std::io::{self, ErrorKind, Read, Write};
+
+use crate::HashMap;
+
+impl HashMap<Vec<u8>, Vec<u8>> {
+    /// read_from reads a map written by `write_to`. The stream is a sequence of
+    /// `[u32 key_len][key][u32 val_len][val]` records with big-endian lengths,
+    /// and is read until EOF.
+    pub fn read_from<R: Read>(r: &mut R) -> io::Result<HashMap<Vec<u8>, Vec<u8>>> {
+        let mut m = HashMap::new();
+        while let Some(key_len) = read_opt_len(r)? {
+            let key = read_bytes(r, key_len)?;
+            let val_len = read_len(r)?;
+            let val = read_bytes(r, val_len)?;
+            m.insert(key, val);
+        }
+        Ok(m)
+    }
+}
+
+impl<S> HashMap<Vec<u8>, Vec<u8>, S>
+where
+    S: BuildHasher,
+{
+    /// write_to writes all elements to w in the format read by `read_from`.
+    pub fn write_to<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        for (k, v) in self.iter() {
+            write_bytes(w, k)?;
+            write_bytes(w, v)?;
+        }
+        Ok(())
+    }
+}
+
+/// read_len reads a u32 length prefix.
+fn read_len<R: Read>(r: &mut R) -> io::Result<usize> {
+    read_opt_len(r)?.ok_or_else(|| ErrorKind::UnexpectedEof.into())
+}
+
+/// read_opt_len is like `read_len`, but returns `None` if the stream ended
+/// before the first byte of the prefix.
+fn read_opt_len<R: Read>(r: &mut R) -> io::Result<Option<usize>> {
+    let mut buf = [0_u8; 4];
+    let mut n = 0;
+    while n < buf.len() {
+        match r.read(&mut buf[n..]) {
+            Ok(0) if n == 0 => return Ok(None),
+            Ok(0) => return Err(ErrorKind::UnexpectedEof.into()),
+            Ok(i) => n += i,
+            Err(e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(Some(u32::from_be_bytes(buf) as usize))
+}
+
+/// read_bytes reads len bytes. The buffer grows with the data actually read,
+/// so a corrupt length can't force a huge allocation up front.
+fn read_bytes<R: Read>(r: &mut R, len: usize) -> io::Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    r.by_ref().take(len as u64).read_to_end(&mut buf)?;
+    if buf.len() < len {
+        return Err(ErrorKind::UnexpectedEof.into());
+    }
+    Ok(buf)
+}
+
+fn write_bytes<W: Write>(w: &mut W, b: &[u8]) -> io::Result<()> {
+    let len = u32::try_from(b.len()).map_err(|_| io::Error::from(ErrorKind::InvalidInput))?;
+    w.write_all(&len.to_be_bytes())?;
+    w.write_all(b)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, ErrorKind};
+
+    use crate::HashMap;
+
+    #[test]
+    fn test_write_read() {
+        let mut m = HashMap::new();
+        for i in 0..1000 {
+            m.insert(i.to_string().into_bytes(), vec![i as u8; i % 7]);
+        }
+
+        let mut buf = Vec::new();
+        m.write_to(&mut buf).unwrap();
+
+        let restored = HashMap::read_from(&mut Cursor::new(buf)).unwrap();
+        assert_eq!(m.len(), restored.len());
+        for (k, v) in m.iter() {
+            assert_eq!(Some(v), restored.get(k));
+        }
+    }
+
+    #[test]
+    fn test_read_truncated() {
+        let mut m = HashMap::new();
+        m.insert(b"key".to_vec(), b"value".to_vec());
+
+        let mut buf = Vec::new();
+        m.write_to(&mut buf).unwrap();
+        buf.pop();
+
+        let err = HashMap::read_from(&mut Cursor::new(buf)).unwrap_err();
+        assert_eq!(ErrorKind::UnexpectedEof, err.kind());
+    }
+
+    #[test]
+    fn test_read_missing_value() {
+        let mut buf = 3_u32.to_be_bytes().to_vec();
+        buf.extend_from_slice(b"key");
+
+        let err = HashMap::read_from(&mut Cursor::new(buf)).unwrap_err();
+        assert_eq!(ErrorKind::UnexpectedEof, err.kind());
+    }
+
+    #[test]
+    fn test_read_corrupt_len() {
+        // A length of 4GiB must not be allocated before the data is there.
+        let mut buf = u32::MAX.to_be_bytes().to_vec();
+        buf.extend_from_slice(b"key");
+
+        let err = HashMap::read_from(&mut Cursor::new(buf)).unwrap_err();
+        assert_eq!(ErrorKind::UnexpectedEof, err.kind());
+    }
+}
@@ -1,7 +1,32 @@
-use std::borrow::Borrow;
+use std::borrow::{Borrow, Cow};
+use std::cmp::Ordering;
+use std::collections::hash_map::RandomState;
+use std::collections::TryReserveError;
 use std::fmt::{Debug, Formatter};
-use std::hash::{Hash, Hasher};
+use std::hash::{BuildHasher, Hash, Hasher};
+use std::iter::FusedIterator;
 use std::mem;
+use std::sync::Arc;
+
+mod array;
+mod bounded;
+mod entry;
+mod incremental;
+mod io;
+mod occupancy;
+#[cfg(feature = "rayon")]
+mod par;
+mod raw;
+mod sharded;
+mod slots;
+
+pub use array::ArrayHashMap;
+pub use bounded::BoundedHashMap;
+pub use entry::{Entry, OccupiedEntry, VacantEntry};
+pub use incremental::IncrementalHashMap;
+pub use occupancy::SparseIter;
+pub use raw::RawSlot;
+pub use sharded::ShardedHashMap;
 
 pub trait AsByte {
     fn as_byte(&self) -> &[u8];
@@ -37,6 +62,70 @@ impl<const N: usize> AsByte for [u8;N] {
     }
 }
 
+/// A reference is hashed like its referent, so maps keyed by references such
+/// as `&str` work and agree with maps keyed by the owned values.
+impl<T: ?Sized + AsByte> AsByte for &T {
+    fn as_byte(&self) -> &[u8] {
+        (**self).as_byte()
+    }
+}
+
+/// CharKey is a char key stored as its UTF-8 encoding. `as_byte` must borrow
+/// from the key, so a char can't be a key itself; a CharKey hashes like the
+/// one-char string, on every platform.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct CharKey {
+    buf: [u8; 4],
+    len: u8,
+}
+
+impl CharKey {
+    pub fn new(c: char) -> Self {
+        let mut buf = [0; 4];
+        let len = c.encode_utf8(&mut buf).len() as u8;
+        Self { buf, len }
+    }
+
+    pub fn as_char(&self) -> char {
+        self.as_str().chars().next().unwrap()
+    }
+
+    pub fn as_str(&self) -> &str {
+        // buf holds the UTF-8 encoding of a char in its first len bytes.
+        std::str::from_utf8(self.as_byte()).unwrap()
+    }
+}
+
+impl AsByte for CharKey {
+    fn as_byte(&self) -> &[u8] {
+        &self.buf[..self.len as usize]
+    }
+}
+
+impl From<char> for CharKey {
+    fn from(c: char) -> Self {
+        Self::new(c)
+    }
+}
+
+/// ByteKey is a fixed-size byte key. Copy-cheap keys such as integers or
+/// composites of them can't hand out their bytes by reference, so encode them
+/// into a ByteKey, e.g. with `to_be_bytes`, and key the map by that.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ByteKey<const N: usize>(pub [u8; N]);
+
+impl<const N: usize> AsByte for ByteKey<N> {
+    fn as_byte(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl<const N: usize> From<[u8; N]> for ByteKey<N> {
+    fn from(b: [u8; N]) -> Self {
+        Self(b)
+    }
+}
+
 struct HashElem<K, V>
 where
     K: Eq + Hash,
@@ -76,7 +165,48 @@ where
     }
 }
 
-pub struct HashMap<K, V>
+/// MAX_CAPACITY is the largest number of slots a map can be created with.
+pub const MAX_CAPACITY: u64 = 1 << 61;
+
+/// Normalizer maps key bytes to the bytes that are hashed and compared.
+pub type Normalizer = fn(&[u8]) -> Cow<'_, [u8]>;
+
+/// GrowHook is called with the old and new capacity whenever a map grows.
+pub type GrowHook = Box<dyn FnMut(u64, u64) + Send + Sync>;
+
+/// XxHash64Builder builds `XxHash64` hashers with a fixed seed. The default
+/// seed is 0, which keeps hashes compatible with InfluxDB.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct XxHash64Builder {
+    seed: u64,
+}
+
+impl XxHash64Builder {
+    pub fn with_seed(seed: u64) -> Self {
+        Self { seed }
+    }
+
+    /// with_random_seed creates a builder with a seed drawn from the entropy
+    /// behind `std::collections::hash_map::RandomState`.
+    pub fn with_random_seed() -> Self {
+        let seed = RandomState::new().build_hasher().finish();
+        Self { seed }
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+}
+
+impl BuildHasher for XxHash64Builder {
+    type Hasher = twox_hash::XxHash64;
+
+    fn build_hasher(&self) -> Self::Hasher {
+        twox_hash::XxHash64::with_seed(self.seed)
+    }
+}
+
+pub struct HashMap<K, V, S = XxHash64Builder>
 where
     K: Eq + Hash + AsByte,
 {
@@ -87,6 +217,21 @@ where
     threshold: u64,
     mask: usize,
     load_factor: usize,
+    // shrink_threshold is the fullness percentage below which `remove`
+    // shrinks the map, if set.
+    shrink_threshold: Option<usize>,
+    // max_probe_distance is the probe distance of a new elem beyond which
+    // inserting grows the map early, if set.
+    max_probe_distance: Option<u64>,
+    on_grow: Option<GrowHook>,
+    growth_policy: GrowthPolicy,
+    // occupied has a bit per slot that is set iff the slot holds an elem, if
+    // the map was created with `with_occupancy_bitmap`.
+    occupied: Option<Vec<u64>>,
+
+    hash_builder: S,
+    normalize: Option<Normalizer>,
+    raw_hash: bool,
 }
 
 impl<K, V> HashMap<K, V>
@@ -94,7 +239,13 @@ where
     K: Eq + Hash + AsByte,
 {
     pub fn new() -> Self {
-        Self::with_capacity(256)
+        Self::with_capacity(Self::DEFAULT_CAPACITY)
+    }
+
+    /// new_small creates a map with `SMALL_CAPACITY` slots, for workloads
+    /// with many small maps such as per-key nested maps.
+    pub fn new_small() -> Self {
+        Self::with_capacity(Self::SMALL_CAPACITY)
     }
 
     pub fn with_capacity(capacity: usize) -> Self {
@@ -102,16 +253,217 @@ where
     }
 
     pub fn with_capacity_and_factor(capacity: usize, load_factor: usize) -> Self {
+        Self::with_capacity_and_hasher_and_factor(capacity, XxHash64Builder::default(), load_factor)
+    }
+
+    /// try_with_capacity is like `with_capacity`, but returns an error instead
+    /// of panicking if capacity exceeds `MAX_CAPACITY`.
+    pub fn try_with_capacity(capacity: usize) -> Result<Self, CapacityTooLarge> {
+        Self::try_with_capacity_and_hasher_and_factor(capacity, XxHash64Builder::default(), 90)
+    }
+
+    /// with_random_seed creates a map whose hash seed is picked at random, so
+    /// that untrusted keys can't be crafted to collide. Unlike `new`, the
+    /// layout and hashes differ between maps and aren't compatible with
+    /// InfluxDB.
+    pub fn with_random_seed() -> Self {
+        Self::with_hasher(XxHash64Builder::with_random_seed())
+    }
+
+    /// with_raw_hash creates a map using the verbatim `XxHash64` output as
+    /// hash, see `with_raw_hasher`.
+    pub fn with_raw_hash() -> Self {
+        Self::with_raw_hasher(XxHash64Builder::default())
+    }
+
+    /// with_normalizer creates a map whose keys are compared and hashed by
+    /// their bytes after applying normalize.
+    pub fn with_normalizer(normalize: Normalizer) -> Self {
+        Self::with_hasher_and_normalizer(XxHash64Builder::default(), normalize)
+    }
+
+    /// from_pairs builds a map from cloned pairs, sized so that inserting them
+    /// never grows the map.
+    pub fn from_pairs(pairs: &[(K, V)]) -> Self
+    where
+        K: Clone,
+        V: Clone,
+    {
+        let mut m = Self::with_capacity(capacity_for(pairs.len() as u64, 90) as usize);
+        for (k, v) in pairs {
+            m.insert(k.clone(), v.clone());
+        }
+        m
+    }
+
+    /// from_sorted_pairs builds a map from pairs with distinct keys, sized so
+    /// that inserting them never grows the map. Sorting doesn't affect
+    /// placement, so the pairs are hashed and inserted as usual; the point is
+    /// to document that the input is already deduplicated.
+    ///
+    /// Panics on a duplicate key in debug builds. In release builds a
+    /// duplicate key overwrites the earlier value.
+    pub fn from_sorted_pairs<I>(pairs: I) -> Self
+    where
+        I: IntoIterator<Item = (K, V)>,
+        I::IntoIter: ExactSizeIterator,
+    {
+        let pairs = pairs.into_iter();
+        let mut m = Self::with_capacity(capacity_for(pairs.len() as u64, 90) as usize);
+        for (k, v) in pairs {
+            let prev = m.insert(k, v);
+            debug_assert!(prev.is_none(), "duplicate key in from_sorted_pairs");
+        }
+        m
+    }
+
+    /// from_keys_with builds a map from keys, computing the value of each key
+    /// with f. The map is sized for the lower bound of the size hint of keys.
+    pub fn from_keys_with<I, F>(keys: I, mut f: F) -> Self
+    where
+        I: IntoIterator<Item = K>,
+        F: FnMut(&K) -> V,
+    {
+        let keys = keys.into_iter();
+        let mut m = Self::with_capacity(capacity_for(keys.size_hint().0 as u64, 90) as usize);
+        for k in keys {
+            let v = f(&k);
+            m.insert(k, v);
+        }
+        m
+    }
+}
+
+impl<K> HashMap<K, ()>
+where
+    K: Eq + Hash + AsByte,
+{
+    /// count_distinct returns the number of distinct items in iter, using a
+    /// temporary map of the owned items.
+    pub fn count_distinct<I, Q>(iter: I) -> u64
+    where
+        I: IntoIterator<Item = Q>,
+        Q: Eq + Hash + AsByte + ToOwned<Owned = K>,
+        K: Borrow<Q>,
+    {
+        let mut m = Self::new();
+        for q in iter {
+            if m.index(&q).is_none() {
+                m.insert(q.to_owned(), ());
+            }
+        }
+        m.len()
+    }
+}
+
+impl<K, V> Default for HashMap<K, V>
+where
+    K: Eq + Hash + AsByte,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, V, S> HashMap<K, V, S>
+where
+    K: Eq + Hash + AsByte,
+    S: BuildHasher,
+{
+    /// DEFAULT_CAPACITY is the number of slots of a map created by `new`. The
+    /// slots are allocated up front, so every map costs 256 times the size of
+    /// a slot even when empty, e.g. about 12KB for `String` keys and `u64`
+    /// values. In exchange, the first couple of hundred inserts never grow
+    /// the map. Use `new_small` or `with_capacity` for many small maps.
+    pub const DEFAULT_CAPACITY: usize = 256;
+
+    /// SMALL_CAPACITY is the number of slots of a map created by `new_small`.
+    pub const SMALL_CAPACITY: usize = 8;
+
+    pub fn with_hasher(hash_builder: S) -> Self {
+        Self::with_capacity_and_hasher(Self::DEFAULT_CAPACITY, hash_builder)
+    }
+
+    pub fn with_capacity_and_hasher(capacity: usize, hash_builder: S) -> Self {
+        Self::with_capacity_and_hasher_and_factor(capacity, hash_builder, 90)
+    }
+
+    /// with_capacity_and_hasher_and_factor creates a map with capacity slots,
+    /// hashing keys with hash_builder and growing once more than load_factor
+    /// percent of the slots are in use.
+    ///
+    /// Panics if capacity exceeds `MAX_CAPACITY`, or if load_factor is not
+    /// within `1..100`.
+    pub fn with_capacity_and_hasher_and_factor(
+        capacity: usize,
+        hash_builder: S,
+        load_factor: usize,
+    ) -> Self {
+        match Self::try_with_capacity_and_hasher_and_factor(capacity, hash_builder, load_factor) {
+            Ok(m) => m,
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    /// try_with_capacity_and_hasher_and_factor is like
+    /// `with_capacity_and_hasher_and_factor`, but returns an error instead of
+    /// panicking if capacity exceeds `MAX_CAPACITY`.
+    ///
+    /// Panics if load_factor is not within `1..100`.
+    pub fn try_with_capacity_and_hasher_and_factor(
+        capacity: usize,
+        hash_builder: S,
+        load_factor: usize,
+    ) -> Result<Self, CapacityTooLarge> {
+        check_load_factor(load_factor);
+
+        // The mask arithmetic needs a power of 2, and pow2 also ensures at
+        // least 2 slots.
+        let capacity = pow2(capacity as u64)? as usize;
         let mut elems = Vec::with_capacity(capacity);
         elems.resize_with(capacity, || None);
-        Self {
+        Ok(Self {
             elems,
             len: 0,
-            capacity: pow2(capacity as u64),
+            capacity: capacity as u64,
             threshold: (capacity as u64 * load_factor as u64) / 100,
             mask: capacity - 1,
             load_factor,
-        }
+            shrink_threshold: None,
+            max_probe_distance: None,
+            on_grow: None,
+            growth_policy: GrowthPolicy::Double,
+            occupied: None,
+            hash_builder,
+            normalize: None,
+            raw_hash: false,
+        })
+    }
+
+    /// with_raw_hasher creates a map that uses the output of hash_builder
+    /// verbatim, skipping the InfluxDB-compatible sign flip and zero remap of
+    /// `hash_key`. This makes the stored hashes reproducible by any xxhash
+    /// implementation. A zero hash is fine, since a slot's occupancy is
+    /// tracked separately from its hash.
+    pub fn with_raw_hasher(hash_builder: S) -> Self {
+        let mut m = Self::with_hasher(hash_builder);
+        m.raw_hash = true;
+        m
+    }
+
+    /// with_hasher_and_normalizer creates a map hashing keys with hash_builder
+    /// after applying normalize to the key bytes. Two keys are then equal iff
+    /// their normalized bytes are equal, e.g. an ASCII-lowercasing normalizer
+    /// makes `"ABC"` and `"abc"` the same key. As always, `insert` stores the
+    /// key it was given when overwriting an equal key.
+    pub fn with_hasher_and_normalizer(hash_builder: S, normalize: Normalizer) -> Self {
+        let mut m = Self::with_hasher(hash_builder);
+        m.normalize = Some(normalize);
+        m
+    }
+
+    pub fn hasher(&self) -> &S {
+        &self.hash_builder
     }
 
     pub fn get<Q>(&self, key: &Q) -> Option<&V>
@@ -123,6 +475,59 @@ where
             .map(|i| &self.elems[i].as_ref().unwrap().value)
     }
 
+    /// index_of returns the slot holding key, in `0..capacity`. The slot is
+    /// only stable until the next mutation of the map, since inserts and
+    /// removals shift elems and grows rehash them.
+    pub fn index_of<Q>(&self, key: &Q) -> Option<usize>
+    where
+        Q: ?Sized + Eq + Hash + AsByte,
+        K: Borrow<Q>,
+    {
+        self.index(key)
+    }
+
+    /// get_with_probes is like `get`, but also returns the number of slots that
+    /// were examined by the lookup, for profiling lookup costs.
+    pub fn get_with_probes<Q>(&self, key: &Q) -> (Option<&V>, u64)
+    where
+        Q: ?Sized + Eq + Hash + AsByte,
+        K: Borrow<Q>,
+    {
+        let hash = self.hash(key);
+        match self.probe(hash, key) {
+            Ok(pos) => (
+                Some(&self.elems[pos].as_ref().unwrap().value),
+                distance(hash, pos, self.capacity) + 1,
+            ),
+            Err((_, dist)) => (None, dist + 1),
+        }
+    }
+
+    /// get_cloned returns a clone of the value of key, so no borrow of the map
+    /// is held.
+    pub fn get_cloned<Q>(&self, key: &Q) -> Option<V>
+    where
+        Q: ?Sized + Eq + Hash + AsByte,
+        K: Borrow<Q>,
+        V: Clone,
+    {
+        self.get(key).cloned()
+    }
+
+    /// get_cloned_or_default returns a clone of the value of key, or the
+    /// default value if key is missing, as if the map were dense.
+    pub fn get_cloned_or_default<Q>(&self, key: &Q) -> V
+    where
+        Q: ?Sized + Eq + Hash + AsByte,
+        K: Borrow<Q>,
+        V: Default + Clone,
+    {
+        self.get(key).cloned().unwrap_or_default()
+    }
+
+    /// get_mut returns a mutable reference to the value of key. It never
+    /// inserts and so never grows the map; the reference stays valid for as
+    /// long as it is borrowed.
     pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
     where
         Q: ?Sized + Eq + Hash + AsByte,
@@ -132,130 +537,1316 @@ where
             .map(|i| &mut self.elems[i].as_mut().unwrap().value)
     }
 
-    pub fn keys(&self) -> Keys<'_, K, V> {
+    /// get_pair_mut returns mutable references to the values of two distinct
+    /// keys. Returns `None` if either key is missing or both keys are equal.
+    pub fn get_pair_mut<Q>(&mut self, a: &Q, b: &Q) -> Option<(&mut V, &mut V)>
+    where
+        Q: ?Sized + Eq + Hash + AsByte,
+        K: Borrow<Q>,
+    {
+        let i = self.index(a)?;
+        let j = self.index(b)?;
+        if i == j {
+            return None;
+        }
+
+        Some(self.values_at_mut(i, j))
+    }
+
+    /// swap_values swaps the values of two keys in place. Returns false if
+    /// either key is missing; swapping a key with itself does nothing.
+    pub fn swap_values<Q>(&mut self, a: &Q, b: &Q) -> bool
+    where
+        Q: ?Sized + Eq + Hash + AsByte,
+        K: Borrow<Q>,
+    {
+        let (i, j) = match (self.index(a), self.index(b)) {
+            (Some(i), Some(j)) => (i, j),
+            _ => return false,
+        };
+        if i != j {
+            let (a, b) = self.values_at_mut(i, j);
+            mem::swap(a, b);
+        }
+        true
+    }
+
+    /// values_at_mut returns the values at two distinct occupied positions.
+    fn values_at_mut(&mut self, i: usize, j: usize) -> (&mut V, &mut V) {
+        let (lo, hi) = self.elems.split_at_mut(i.max(j));
+        let e_lo = &mut lo[i.min(j)].as_mut().unwrap().value;
+        let e_hi = &mut hi[0].as_mut().unwrap().value;
+        if i < j {
+            (e_lo, e_hi)
+        } else {
+            (e_hi, e_lo)
+        }
+    }
+
+    pub fn keys(&self) -> Keys<'_, K, V, S> {
         Keys::new(self.iter())
     }
 
-    pub fn iter(&self) -> Iter<'_, K, V> {
+    pub fn values(&self) -> Values<'_, K, V, S> {
+        Values::new(self.iter())
+    }
+
+    /// iter returns the elements in slot order. The iterator borrows only the
+    /// map, so while it is alive the other fields of a struct containing the
+    /// map can still be borrowed mutably.
+    pub fn iter(&self) -> Iter<'_, K, V, S> {
         Iter::new(self)
     }
 
+    /// iter_by_probe_distance returns the elements with their distance from
+    /// their home slot, from the best to the worst placed. Elements at the
+    /// same distance are in slot order.
+    pub fn iter_by_probe_distance(&self) -> Vec<(&K, &V, u64)> {
+        let mut elems: Vec<_> = self
+            .elems
+            .iter()
+            .enumerate()
+            .filter_map(|(pos, e)| {
+                let e = e.as_ref()?;
+                Some((&e.key, &e.value, distance(e.hash, pos, self.capacity)))
+            })
+            .collect();
+        elems.sort_by_key(|e| e.2);
+        elems
+    }
+
+    /// aggregate folds f over all elements, starting from init.
+    pub fn aggregate<A, F>(&self, init: A, mut f: F) -> A
+    where
+        F: FnMut(A, &K, &V) -> A,
+    {
+        self.iter().fold(init, |acc, (k, v)| f(acc, k, v))
+    }
+
+    /// entries_within_distance iterates the elements whose current probe
+    /// distance is at most max.
+    pub fn entries_within_distance(&self, max: u64) -> impl Iterator<Item = (&K, &V)> {
+        self.entries_by_distance(move |dist| dist <= max)
+    }
+
+    /// entries_beyond_distance iterates the elements whose current probe
+    /// distance is greater than min, i.e. the complement of
+    /// `entries_within_distance`.
+    pub fn entries_beyond_distance(&self, min: u64) -> impl Iterator<Item = (&K, &V)> {
+        self.entries_by_distance(move |dist| dist > min)
+    }
+
+    fn entries_by_distance<F>(&self, f: F) -> impl Iterator<Item = (&K, &V)>
+    where
+        F: Fn(u64) -> bool,
+    {
+        self.elems.iter().enumerate().filter_map(move |(pos, e)| {
+            e.as_ref()
+                .filter(|e| f(distance(e.hash, pos, self.capacity)))
+                .map(|e| (&e.key, &e.value))
+        })
+    }
+
+    /// min_key_value returns the element with the smallest key bytes.
+    pub fn min_key_value(&self) -> Option<(&K, &V)> {
+        self.iter().min_by(|a, b| a.0.as_byte().cmp(b.0.as_byte()))
+    }
+
+    /// max_key_value returns the element with the largest key bytes.
+    pub fn max_key_value(&self) -> Option<(&K, &V)> {
+        self.iter().max_by(|a, b| a.0.as_byte().cmp(b.0.as_byte()))
+    }
+
+    /// iter_owned iterates clones of all elements, e.g. for moving them into
+    /// a `'static` task without holding a borrow of the map beyond the
+    /// iteration.
+    pub fn iter_owned(&self) -> impl Iterator<Item = (K, V)> + '_
+    where
+        K: Clone,
+        V: Clone,
+    {
+        self.iter().map(|(k, v)| (k.clone(), v.clone()))
+    }
+
     pub fn iter_mut(&mut self) -> impl Iterator<Item = (&'_ K, &'_ mut V)> {
         self.elems
             .iter_mut()
             .filter_map(|e| e.as_mut().map(|e| (&e.key, &mut e.value)))
     }
 
-    pub fn insert(&mut self, key: K, val: V) {
+    /// insert inserts val for key, and returns the previous value if the key
+    /// was already present.
+    pub fn insert(&mut self, key: K, val: V) -> Option<V> {
+        self.insert_reporting(key, val).previous
+    }
+
+    /// insert_reporting is like `insert`, but also reports where the elem
+    /// ended up.
+    pub fn insert_reporting(&mut self, key: K, val: V) -> InsertResult<V> {
         // Grow the map if we've run out of slots.
         if self.len > self.threshold {
             self.grow();
         }
 
-        // If the key was overwritten then decrement the size.
-        let _overwritten = self.insert_raw(hash_key(&key), key, val);
+        let hash = self.hash(&key);
+        let mut probed = self.probe(hash, &key);
+        while let Err((pos, dist)) = probed {
+            if !self.probe_too_long(pos, dist) {
+                break;
+            }
+            self.grow();
+            probed = self.probe(hash, &key);
+        }
+        let (previous, slot) = self.insert_probed(probed, hash, key, val);
+        InsertResult {
+            previous,
+            slot,
+            probe_distance: self.elems[slot].as_ref().unwrap().dist,
+        }
     }
 
-    fn grow(&mut self) {
-        let mut new_map =
-            Self::with_capacity_and_factor((self.capacity * 2) as usize, self.load_factor);
-        for e in &mut self.elems {
-            let e = e.take();
-            if let Some(HashElem {
-                hash, key, value, ..
-            }) = e
-            {
-                new_map.insert_raw(hash, key, value);
-            }
+    /// put_if inserts val for key if key is missing, or if should_replace
+    /// returns true for the present value and val, e.g. to keep the newest
+    /// version. Returns whether val was stored.
+    pub fn put_if<F>(&mut self, key: K, val: V, should_replace: F) -> bool
+    where
+        F: FnOnce(&V, &V) -> bool,
+    {
+        if self.len > self.threshold {
+            self.grow();
         }
 
-        mem::swap(&mut new_map, self);
+        let hash = self.hash(&key);
+        let mut probed = self.probe(hash, &key);
+        if let Ok(pos) = probed {
+            if !should_replace(&self.elems[pos].as_ref().unwrap().value, &val) {
+                return false;
+            }
+        }
+        while let Err((pos, dist)) = probed {
+            if !self.probe_too_long(pos, dist) {
+                break;
+            }
+            self.grow();
+            probed = self.probe(hash, &key);
+        }
+        self.insert_probed(probed, hash, key, val);
+        true
     }
 
-    fn insert_raw(&mut self, hash: u64, key: K, val: V) -> bool {
-        let mut pos = (hash & self.mask as u64) as usize;
-
-        let mut dist = 0_u64;
-        let mut entry = HashElem::new(dist, key, val, hash);
+    /// insert_bounded is like `insert`, but refuses to insert a new key if the
+    /// new elem, or any elem it displaces, would end up more than max_probe
+    /// slots away from its home slot. The bound is checked against the
+    /// current layout, before the map grows as `insert` would, so on error the
+    /// map is left untouched. Only a map without a single free slot, which
+    /// small maps can reach at their threshold, has to grow first.
+    pub fn insert_bounded(
+        &mut self,
+        key: K,
+        val: V,
+        max_probe: u64,
+    ) -> Result<Option<V>, ProbeTooLong> {
+        if self.len == self.capacity {
+            self.grow();
+        }
 
-        // Continue searching until we find an empty slot or lower probe distance.
-        loop {
-            // Empty slot found or matching key, insert and exit.
-            entry.dist = dist;
-            match &self.elems[pos] {
-                Some(e) => {
-                    if e.key.eq(&entry.key) {
-                        self.elems[pos] = Some(entry);
-                        return true;
-                    }
+        let hash = self.hash(&key);
+        match self.probe(hash, &key) {
+            Ok(pos) => {
+                let e = self.elems[pos].as_mut().unwrap();
+                e.key = key;
+                Ok(Some(mem::replace(&mut e.value, val)))
+            }
+            Err((pos, dist)) => {
+                let probe = self.max_shift_distance(pos, dist);
+                if probe > max_probe {
+                    return Err(ProbeTooLong { probe, max_probe });
                 }
-                None => {
-                    self.elems[pos] = Some(entry);
-                    self.len += 1;
-                    return false;
+
+                // The bound holds for the current layout, so the elem is placed
+                // there first and the map grown afterwards.
+                let grow = self.len > self.threshold;
+                self.insert_at(pos, dist, hash, key, val);
+                if grow {
+                    self.grow();
                 }
+                Ok(None)
             }
+        }
+    }
 
-            let e = self.elems[pos].as_mut().unwrap();
-            // println!("collision: {:?} <=> {:?}", &e.key, &entry.key);
+    /// set_many inserts all pairs of items, overwriting existing keys. If a key
+    /// occurs more than once in items, the last value wins. Room for the lower
+    /// bound of the size hint is reserved up front, as in `extend`.
+    pub fn set_many<I>(&mut self, items: I)
+    where
+        I: IntoIterator<Item = (K, V)>,
+    {
+        self.extend(items);
+    }
 
-            // If the existing elem has probed less than us, then swap places with
-            // existing elem, and keep going to find another slot for that elem.
-            let elem_dist = distance(e.hash, pos, self.capacity);
-            if elem_dist < dist {
-                // Update current distance.
-                dist = elem_dist;
+    /// insert_with_eviction is like `insert`, but never grows the map. When a
+    /// new key is inserted into a map that would otherwise grow, the element
+    /// with the longest probe distance is removed first and passed to
+    /// on_evict. Returns the previous value of key.
+    pub fn insert_with_eviction<F>(&mut self, key: K, val: V, mut on_evict: F) -> Option<V>
+    where
+        F: FnMut(K, V),
+    {
+        let hash = self.hash(&key);
+        let probed = self.probe(hash, &key);
+        if probed.is_ok() || self.len <= self.threshold {
+            return self.insert_probed(probed, hash, key, val).0;
+        }
 
-                entry.dist = dist;
-                mem::swap(e, &mut entry);
-            }
+        let victim = self
+            .elems
+            .iter()
+            .enumerate()
+            .filter_map(|(pos, e)| e.as_ref().map(|e| (pos, e.dist)))
+            .max_by_key(|&(_, dist)| dist)
+            .map(|(pos, _)| pos)
+            .unwrap();
+        let e = self.remove_at(victim);
+        on_evict(e.key, e.value);
 
-            pos = (pos + 1) & self.mask;
-            dist += 1;
+        // Removing shifted elems, so probe again.
+        self.insert_raw(hash, key, val)
+    }
+
+    /// entry returns the entry for key, for in-place manipulation. The key is
+    /// hashed and probed exactly once; the resulting slot (or the insertion
+    /// point for a vacant key) is carried by the entry.
+    pub fn entry(&mut self, key: K) -> Entry<'_, K, V, S> {
+        // Grow up front so a vacant entry's insertion point stays valid.
+        if self.len > self.threshold {
+            self.grow();
+        }
+
+        let hash = self.hash(&key);
+        match self.probe(hash, &key) {
+            Ok(index) => Entry::Occupied(OccupiedEntry::new(self, index)),
+            Err((pos, dist)) => Entry::Vacant(VacantEntry::new(self, hash, key, pos, dist)),
         }
     }
 
-    /// index returns the position of key in the hash map.
-    fn index<Q>(&self, key: &Q) -> Option<usize>
+    /// map_values_in_place calls f on every value.
+    pub fn map_values_in_place<F>(&mut self, mut f: F)
     where
-        Q: ?Sized + Eq + Hash + AsByte,
-        K: Borrow<Q>,
+        F: FnMut(&mut V),
     {
-        let hash = hash_key(key);
-        let mut pos = (hash & self.mask as u64) as usize;
-
-        let mut dist = 0_u64;
-        loop {
-            let e = self.elems[pos].as_ref()?;
-            if dist > distance(e.hash, pos, self.capacity) {
-                return None;
-            } else if e.hash == hash && key.borrow().eq(e.key.borrow()) {
-                return Some(pos);
-            }
-
-            pos = (pos + 1) & self.mask;
-            dist += 1;
-        }
+        self.iter_mut().for_each(|(_, v)| f(v));
     }
 
-    pub fn len(&self) -> u64 {
-        self.len
+    /// to_arc_entries returns a shareable snapshot of all elements, e.g. for
+    /// handing out to readers in a copy-on-write scheme.
+    pub fn to_arc_entries(&self) -> Arc<[(K, V)]>
+    where
+        K: Clone,
+        V: Clone,
+    {
+        self.iter().map(|(k, v)| (k.clone(), v.clone())).collect()
     }
 
-    pub fn capacity(&self) -> u64 {
-        self.capacity
+    /// into_vec consumes the map and returns all elements, in slot order.
+    pub fn into_vec(self) -> Vec<(K, V)> {
+        let mut v = Vec::with_capacity(self.len as usize);
+        v.extend(self.elems.into_iter().flatten().map(|e| (e.key, e.value)));
+        v
     }
-}
 
-impl<K, V> Debug for HashMap<K, V>
-where
-    K: Eq + Hash + AsByte + Debug,
-    V: Debug,
-{
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        f.debug_map().entries(self.iter()).finish()
+    /// remove removes key from the map, and returns its value if it was
+    /// present.
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        Q: ?Sized + Eq + Hash + AsByte,
+        K: Borrow<Q>,
+    {
+        let val = self.index(key).map(|pos| self.remove_at(pos).value);
+        self.shrink_if_sparse();
+        val
     }
-}
+
+    /// remove_if removes key and returns its value if it is present and pred
+    /// returns true for the value, e.g. to evict a cache entry only if it is
+    /// still the one that was checked.
+    pub fn remove_if<Q, F>(&mut self, key: &Q, pred: F) -> Option<V>
+    where
+        Q: ?Sized + Eq + Hash + AsByte,
+        K: Borrow<Q>,
+        F: FnOnce(&V) -> bool,
+    {
+        let pos = self.index(key)?;
+        if !pred(&self.elems[pos].as_ref().unwrap().value) {
+            return None;
+        }
+        let val = self.remove_at(pos).value;
+        self.shrink_if_sparse();
+        Some(val)
+    }
+
+    /// remove_all removes every key in keys, and returns how many of them were
+    /// present.
+    pub fn remove_all<'a, Q, I>(&mut self, keys: I) -> usize
+    where
+        I: IntoIterator<Item = &'a Q>,
+        Q: 'a + ?Sized + Eq + Hash + AsByte,
+        K: Borrow<Q>,
+    {
+        keys.into_iter()
+            .filter(|k| self.remove(*k).is_some())
+            .count()
+    }
+
+    /// compute calls f with the current value of key, if any, and stores the
+    /// result: `Some` inserts or replaces the value, `None` removes the key.
+    pub fn compute<F>(&mut self, key: K, f: F)
+    where
+        F: FnOnce(Option<V>) -> Option<V>,
+    {
+        let hash = self.hash(&key);
+        match self.probe(hash, &key) {
+            Ok(pos) => {
+                // The elem is taken out to hand its value to f, and put back
+                // with its stored hash if f keeps it.
+                let e = self.remove_at(pos);
+                match f(Some(e.value)) {
+                    Some(v) => {
+                        self.insert_raw(e.hash, key, v);
+                    }
+                    None => self.shrink_if_sparse(),
+                }
+            }
+            Err(_) => {
+                if let Some(v) = f(None) {
+                    self.insert(key, v);
+                }
+            }
+        }
+    }
+
+    /// compute_with is like `compute`, but f also gets to read the whole map
+    /// and always stores a value. It runs in two phases: f sees the map as it
+    /// is before the call, and only once f returns is its result written,
+    /// which may grow the map.
+    pub fn compute_with<F>(&mut self, key: K, f: F)
+    where
+        F: FnOnce(&Self, Option<&V>) -> V,
+    {
+        let hash = self.hash(&key);
+        match self.probe(hash, &key) {
+            Ok(pos) => {
+                let v = f(self, Some(&self.elems[pos].as_ref().unwrap().value));
+                self.elems[pos].as_mut().unwrap().value = v;
+            }
+            Err(_) => {
+                let v = f(self, None);
+                self.insert(key, v);
+            }
+        }
+    }
+
+    /// compute_if_present calls f with the element of key if it is present.
+    /// If f returns false the element is removed, otherwise a mutable
+    /// reference to the possibly modified value is returned.
+    pub fn compute_if_present<Q, F>(&mut self, key: &Q, f: F) -> Option<&mut V>
+    where
+        Q: ?Sized + Eq + Hash + AsByte,
+        K: Borrow<Q>,
+        F: FnOnce(&K, &mut V) -> bool,
+    {
+        let pos = self.index(key)?;
+        let e = self.elems[pos].as_mut().unwrap();
+        if f(&e.key, &mut e.value) {
+            return Some(&mut self.elems[pos].as_mut().unwrap().value);
+        }
+
+        self.remove_at(pos);
+        self.shrink_if_sparse();
+        None
+    }
+
+    /// remove_returning_shifted is like `remove`, but also returns the
+    /// positions of the elems that were shifted back by one slot to close the
+    /// gap, in shifting order. Mostly useful for tests and instrumentation.
+    pub fn remove_returning_shifted<Q>(&mut self, key: &Q) -> Option<(V, Vec<usize>)>
+    where
+        Q: ?Sized + Eq + Hash + AsByte,
+        K: Borrow<Q>,
+    {
+        let pos = self.index(key)?;
+        let mut shifted = Vec::new();
+        let e = self.remove_at_with(pos, |pos| shifted.push(pos));
+        Some((e.value, shifted))
+    }
+
+    /// remove_at removes the elem at pos, then shifts the following displaced
+    /// elems back by one slot so no probe chain is broken.
+    fn remove_at(&mut self, pos: usize) -> HashElem<K, V> {
+        self.remove_at_with(pos, |_| {})
+    }
+
+    /// remove_at_with is like `remove_at`, but calls on_shift with the
+    /// position of every elem before it is shifted.
+    fn remove_at_with<F>(&mut self, pos: usize, on_shift: F) -> HashElem<K, V>
+    where
+        F: FnMut(usize),
+    {
+        let (removed, vacated) = slots::remove(&mut self.elems, pos, on_shift);
+        self.set_occupied(vacated, false);
+        self.len -= 1;
+        removed
+    }
+
+    /// clear removes all elements, keeping the allocated capacity.
+    pub fn clear(&mut self) {
+        if self.len == 0 {
+            return;
+        }
+        self.elems.iter_mut().for_each(|e| *e = None);
+        self.reset_occupancy();
+        self.len = 0;
+    }
+
+    /// clear_and_shrink removes all elements and releases the backing array,
+    /// leaving the map with the default capacity. A map that is already
+    /// smaller than that keeps its capacity.
+    pub fn clear_and_shrink(&mut self) {
+        self.elems = Vec::new();
+        self.len = 0;
+        self.resize(self.capacity.min(Self::DEFAULT_CAPACITY as u64));
+    }
+
+    /// retain keeps only the elements for which f returns true. The remaining
+    /// elements are rehashed into a fresh array of the same capacity. An empty
+    /// map is left alone.
+    pub fn retain<F>(&mut self, mut f: F)
+    where
+        F: FnMut(&K, &mut V) -> bool,
+    {
+        if self.len == 0 {
+            return;
+        }
+        self.rebuild(self.capacity, |k, v, _| f(k, v));
+    }
+
+    /// retain_in_place is like `retain`, but removes elements from the current
+    /// array as it scans, shifting the rest back, instead of allocating a
+    /// second array. The capacity is kept.
+    pub fn retain_in_place<F>(&mut self, mut f: F)
+    where
+        F: FnMut(&K, &mut V) -> bool,
+    {
+        if self.len == 0 {
+            return;
+        }
+
+        // Scan from just past an empty slot. A removal only shifts back the
+        // elems after it up to the next empty slot, so every elem shifted
+        // into the cursor or beyond it has not been visited yet.
+        let start = match self.elems.iter().position(Option::is_none) {
+            Some(start) => start,
+            None => return self.retain(f),
+        };
+
+        let mut pos = (start + 1) & self.mask;
+        let mut remaining = self.capacity - 1;
+        while remaining > 0 {
+            let keep = match &mut self.elems[pos] {
+                Some(e) => f(&e.key, &mut e.value),
+                None => true,
+            };
+            if keep {
+                pos = (pos + 1) & self.mask;
+                remaining -= 1;
+            } else {
+                // The next elem is shifted into pos, so look at pos again.
+                self.remove_at(pos);
+            }
+        }
+    }
+
+    /// retain_mut is `retain` for callers that edit values while scanning: f
+    /// may modify each value before deciding whether to keep it, so editing
+    /// and deleting take a single pass over the map.
+    pub fn retain_mut<F>(&mut self, f: F)
+    where
+        F: FnMut(&K, &mut V) -> bool,
+    {
+        self.retain(f);
+    }
+
+    /// retain_with_meta is like `retain`, but f is also given the current
+    /// probe distance of each element, e.g. to prune badly placed elements.
+    pub fn retain_with_meta<F>(&mut self, f: F)
+    where
+        F: FnMut(&K, &mut V, u64) -> bool,
+    {
+        if self.len == 0 {
+            return;
+        }
+        self.rebuild(self.capacity, f);
+    }
+
+    /// rebuild_map passes every element to f by value, and reinserts the
+    /// returned pair into a fresh array of the same capacity, or drops the
+    /// element if f returns `None`. f may change the key, e.g. to rename
+    /// prefixes. If two elements end up with the same key, one of them is
+    /// dropped; which one is unspecified.
+    pub fn rebuild_map<F>(&mut self, mut f: F)
+    where
+        F: FnMut(K, V) -> Option<(K, V)>,
+    {
+        if self.len == 0 {
+            return;
+        }
+        for e in self.take_elems() {
+            if let Some((key, val)) = f(e.key, e.value) {
+                let hash = self.hash(&key);
+                self.insert_raw(hash, key, val);
+            }
+        }
+    }
+
+    /// drain_filter_collect removes the elements for which pred returns true,
+    /// and returns them. The remaining elements are reinserted into a fresh
+    /// array of the same capacity.
+    pub fn drain_filter_collect<F>(&mut self, mut pred: F) -> Vec<(K, V)>
+    where
+        F: FnMut(&K, &V) -> bool,
+    {
+        if self.len == 0 {
+            return Vec::new();
+        }
+
+        let mut drained = Vec::new();
+        for e in self.take_elems() {
+            if pred(&e.key, &e.value) {
+                drained.push((e.key, e.value));
+            } else {
+                self.insert_raw(e.hash, e.key, e.value);
+            }
+        }
+        drained
+    }
+
+    /// take_elems empties the map, keeping its capacity, and returns the
+    /// elements that were in it.
+    fn take_elems(&mut self) -> impl Iterator<Item = HashElem<K, V>> {
+        let mut new_elems = Vec::with_capacity(self.capacity as usize);
+        new_elems.resize_with(self.capacity as usize, || None);
+        let elems = mem::replace(&mut self.elems, new_elems);
+        self.reset_occupancy();
+        self.len = 0;
+        elems.into_iter().flatten()
+    }
+
+    /// retain_and_shrink is like `retain`, but also calls `shrink_to_fit` if
+    /// the map is less full than the shrink threshold afterwards, or a quarter
+    /// full if no threshold is set.
+    pub fn retain_and_shrink<F>(&mut self, f: F)
+    where
+        F: FnMut(&K, &mut V) -> bool,
+    {
+        self.retain(f);
+        let threshold = self.shrink_threshold.unwrap_or(25) as u64;
+        if self.len * 100 < self.capacity * threshold {
+            self.shrink_to_fit();
+        }
+    }
+
+    /// reserve grows the map so that at least additional more elements can
+    /// be inserted without growing again.
+    ///
+    /// Panics if the new capacity overflows.
+    pub fn reserve(&mut self, additional: usize) {
+        let len = self
+            .len
+            .checked_add(additional as u64)
+            .expect("capacity overflow");
+        let capacity = capacity_for(len, self.load_factor);
+        if capacity > self.capacity {
+            self.resize(capacity);
+        }
+    }
+
+    /// reserve_exact is `reserve`: the capacity must be a power of 2, so the
+    /// smallest capacity holding additional more elements is already what
+    /// `reserve` picks, and there is no headroom to leave out.
+    pub fn reserve_exact(&mut self, additional: usize) {
+        self.reserve(additional);
+    }
+
+    /// try_reserve is like `reserve`, but returns an error instead of aborting
+    /// if the capacity overflows or the allocator reports a failure. The map
+    /// is left unchanged on error.
+    pub fn try_reserve(&mut self, additional: usize) -> Result<(), TryReserveError> {
+        let capacity = (self.len as usize)
+            .checked_add(additional)
+            .and_then(|len| checked_capacity_for(len as u64, self.load_factor));
+        let capacity = match capacity {
+            Some(capacity) => capacity,
+            // Let Vec report the overflow, TryReserveError can't be built here.
+            None => return Vec::<Option<HashElem<K, V>>>::new().try_reserve_exact(usize::MAX),
+        };
+        if capacity <= self.capacity {
+            return Ok(());
+        }
+
+        let mut new_elems = Vec::new();
+        new_elems.try_reserve_exact(capacity as usize)?;
+        new_elems.resize_with(capacity as usize, || None);
+        self.rebuild_into(new_elems, |_, _, _| true);
+        Ok(())
+    }
+
+    /// shrink_to_fit shrinks the capacity to the smallest power of 2 that
+    /// holds the current elements within the load factor. An empty map
+    /// shrinks to the minimum capacity of 2 slots; once there, further calls
+    /// neither allocate nor scan.
+    pub fn shrink_to_fit(&mut self) {
+        let capacity = capacity_for(self.len, self.load_factor);
+        if capacity < self.capacity {
+            self.resize(capacity);
+        }
+    }
+
+    /// shrink_if_sparse calls `shrink_to_fit` if the map is less full than the
+    /// shrink threshold, if one is set.
+    fn shrink_if_sparse(&mut self) {
+        if let Some(threshold) = self.shrink_threshold {
+            if self.len * 100 < self.capacity * threshold as u64 {
+                self.shrink_to_fit();
+            }
+        }
+    }
+
+    fn grow(&mut self) {
+        let old_capacity = self.capacity;
+        let factor = match self.growth_policy {
+            GrowthPolicy::Double => 2,
+            GrowthPolicy::Tiered {
+                cutoff_bytes,
+                small_factor,
+                large_factor,
+            } => {
+                let bytes = self.elems.len() * mem::size_of::<Option<HashElem<K, V>>>();
+                if bytes < cutoff_bytes {
+                    small_factor
+                } else {
+                    large_factor
+                }
+            }
+        };
+        self.resize(grown_capacity(old_capacity, factor));
+        if let Some(on_grow) = &mut self.on_grow {
+            on_grow(old_capacity, self.capacity);
+        }
+    }
+
+    /// resize rehashes all elements into a new array of capacity slots.
+    /// Capacity must be a power of 2.
+    fn resize(&mut self, capacity: u64) {
+        self.rebuild(capacity, |_, _, _| true);
+    }
+
+    /// rebuild reinserts the elements for which f returns true into a new
+    /// array of capacity slots. f is given each element with its probe
+    /// distance in the old array. Capacity must be a power of 2.
+    fn rebuild<F>(&mut self, capacity: u64, f: F)
+    where
+        F: FnMut(&K, &mut V, u64) -> bool,
+    {
+        let mut new_elems = Vec::with_capacity(capacity as usize);
+        new_elems.resize_with(capacity as usize, || None);
+        self.rebuild_into(new_elems, f);
+    }
+
+    /// rebuild_into is like `rebuild`, but reinserts into the given empty
+    /// array, whose length must be a power of 2.
+    fn rebuild_into<F>(&mut self, new_elems: Vec<Option<HashElem<K, V>>>, mut f: F)
+    where
+        F: FnMut(&K, &mut V, u64) -> bool,
+    {
+        let old_capacity = self.capacity;
+
+        let capacity = new_elems.len() as u64;
+        let elems = mem::replace(&mut self.elems, new_elems);
+        self.len = 0;
+        self.capacity = capacity;
+        self.threshold = (self.capacity * self.load_factor as u64) / 100;
+        self.mask = self.capacity as usize - 1;
+        self.reset_occupancy();
+
+        for (pos, e) in elems.into_iter().enumerate() {
+            let HashElem {
+                hash,
+                key,
+                mut value,
+                ..
+            } = match e {
+                Some(e) => e,
+                // Removal shifts elems back rather than leaving tombstones,
+                // so every slot is either empty or live.
+                None => continue,
+            };
+            if f(&key, &mut value, distance(hash, pos, old_capacity)) {
+                self.insert_raw(hash, key, value);
+            }
+        }
+    }
+
+    fn insert_raw(&mut self, hash: u64, key: K, val: V) -> Option<V> {
+        let probed = self.probe(hash, &key);
+        self.insert_probed(probed, hash, key, val).0
+    }
+
+    /// insert_probed is like `insert_raw`, but takes the result of probing for
+    /// the key. Also returns the position of the elem.
+    fn insert_probed(
+        &mut self,
+        probed: Result<usize, (usize, u64)>,
+        hash: u64,
+        key: K,
+        val: V,
+    ) -> (Option<V>, usize) {
+        match probed {
+            Ok(pos) => {
+                let e = self.elems[pos].as_mut().unwrap();
+                e.key = key;
+                (Some(mem::replace(&mut e.value, val)), pos)
+            }
+            Err((pos, dist)) => (None, self.insert_at(pos, dist, hash, key, val)),
+        }
+    }
+
+    /// probe_too_long reports whether inserting a new elem at the insertion
+    /// point pos should make the map grow early, because the new elem or one
+    /// it displaces would exceed the maximum probe distance. To keep keys that
+    /// collide no matter the capacity from growing the map without bound, this
+    /// never happens while less than a quarter of the slots are in use.
+    fn probe_too_long(&self, pos: usize, dist: u64) -> bool {
+        match self.max_probe_distance {
+            Some(max) => self.len * 4 >= self.capacity && self.max_shift_distance(pos, dist) > max,
+            None => false,
+        }
+    }
+
+    /// max_shift_distance returns the largest probe distance any elem would
+    /// have after inserting a new elem at the insertion point pos, without
+    /// modifying the map.
+    fn max_shift_distance(&self, pos: usize, dist: u64) -> u64 {
+        let mut max = dist;
+        let mut pos = pos;
+
+        // Distance of the elem carried forward by the shift.
+        let mut dist = match &self.elems[pos] {
+            Some(e) => distance(e.hash, pos, self.capacity),
+            None => return max,
+        };
+        loop {
+            pos = (pos + 1) & self.mask;
+            dist += 1;
+
+            let e = match &self.elems[pos] {
+                Some(e) => e,
+                None => return max.max(dist),
+            };
+
+            let elem_dist = distance(e.hash, pos, self.capacity);
+            if elem_dist < dist {
+                max = max.max(dist);
+                dist = elem_dist;
+            }
+        }
+    }
+
+    /// insert_at places a new elem at the insertion point pos found by
+    /// `probe`, shifting displaced elems toward their next free slot.
+    /// Returns the position of the new elem, which is always pos.
+    fn insert_at(&mut self, pos: usize, dist: u64, hash: u64, key: K, val: V) -> usize {
+        let filled = slots::insert(
+            &mut self.elems,
+            pos,
+            dist,
+            HashElem::new(dist, key, val, hash),
+        );
+        self.set_occupied(filled, true);
+        self.len += 1;
+        pos
+    }
+
+    /// probe searches key in the hash map. Returns `Ok` with the position of key
+    /// if it exists, otherwise `Err` with the position and probe distance at
+    /// which key would be inserted.
+    fn probe<Q>(&self, hash: u64, key: &Q) -> Result<usize, (usize, u64)>
+    where
+        Q: ?Sized + Eq + Hash + AsByte,
+        K: Borrow<Q>,
+    {
+        slots::probe(&self.elems, hash, |k| self.key_eq(key, k.borrow()))
+    }
+
+    /// index returns the position of key in the hash map.
+    fn index<Q>(&self, key: &Q) -> Option<usize>
+    where
+        Q: ?Sized + Eq + Hash + AsByte,
+        K: Borrow<Q>,
+    {
+        let hash = self.hash(key);
+
+        // Most keys sit in their home slot, so check it before probing.
+        let home = (hash & self.mask as u64) as usize;
+        if let Some(e) = &self.elems[home] {
+            if e.hash == hash && self.key_eq(key, e.key.borrow()) {
+                return Some(home);
+            }
+        }
+
+        self.probe(hash, key).ok()
+    }
+
+    /// clone_with_capacity clones the map into an array of capacity slots,
+    /// rounded up to a power of 2 and to what the elements need under the
+    /// load factor. Useful for a right-sized copy of an oversized map.
+    ///
+    /// Panics if capacity exceeds `MAX_CAPACITY`.
+    pub fn clone_with_capacity(&self, capacity: usize) -> Self
+    where
+        K: Clone,
+        V: Clone,
+        S: Clone,
+    {
+        let capacity = match pow2(capacity as u64) {
+            Ok(c) => c.max(capacity_for(self.len, self.load_factor)),
+            Err(e) => panic!("{}", e),
+        };
+        let mut m = self.empty_like(capacity);
+        for e in self.elems.iter().flatten() {
+            m.insert_raw(e.hash, e.key.clone(), e.value.clone());
+        }
+        m
+    }
+
+    /// map_into converts the map into one holding f of each value. The keys
+    /// keep their slots, so nothing is rehashed or moved.
+    pub fn map_into<V2, F>(self, mut f: F) -> HashMap<K, V2, S>
+    where
+        F: FnMut(V) -> V2,
+    {
+        let elems = self
+            .elems
+            .into_iter()
+            .map(|e| e.map(|e| HashElem::new(e.dist, e.key, f(e.value), e.hash)))
+            .collect();
+        HashMap {
+            elems,
+            len: self.len,
+            capacity: self.capacity,
+            threshold: self.threshold,
+            mask: self.mask,
+            load_factor: self.load_factor,
+            shrink_threshold: self.shrink_threshold,
+            max_probe_distance: self.max_probe_distance,
+            on_grow: self.on_grow,
+            growth_policy: self.growth_policy,
+            occupied: self.occupied,
+            hash_builder: self.hash_builder,
+            normalize: self.normalize,
+            raw_hash: self.raw_hash,
+        }
+    }
+
+    /// empty_like creates an empty map of capacity slots, configured like this
+    /// map. Capacity must be a power of 2.
+    fn empty_like(&self, capacity: u64) -> Self
+    where
+        S: Clone,
+    {
+        let mut m = Self::with_capacity_and_hasher_and_factor(
+            capacity as usize,
+            self.hash_builder.clone(),
+            self.load_factor,
+        );
+        m.shrink_threshold = self.shrink_threshold;
+        m.max_probe_distance = self.max_probe_distance;
+        m.normalize = self.normalize;
+        m.raw_hash = self.raw_hash;
+        m.growth_policy = self.growth_policy;
+        if self.occupied.is_some() {
+            m.occupied = Some(Vec::new());
+            m.reset_occupancy();
+        }
+        m
+    }
+
+    /// hash computes the hash of key with the map's hash builder.
+    fn hash<Q>(&self, key: &Q) -> u64
+    where
+        Q: ?Sized + Eq + Hash + AsByte,
+    {
+        let hash = if self.raw_hash {
+            raw_hash_key_with::<S, [u8]>
+        } else {
+            hash_key_with::<S, [u8]>
+        };
+        match self.normalize {
+            Some(normalize) => hash(&self.hash_builder, normalize(key.as_byte()).as_ref()),
+            None => hash(&self.hash_builder, key.as_byte()),
+        }
+    }
+
+    /// key_eq reports whether two keys are equal, comparing normalized bytes
+    /// if the map has a normalizer.
+    fn key_eq<Q>(&self, a: &Q, b: &Q) -> bool
+    where
+        Q: ?Sized + Eq + Hash + AsByte,
+    {
+        match self.normalize {
+            Some(normalize) => normalize(a.as_byte()) == normalize(b.as_byte()),
+            None => a.eq(b),
+        }
+    }
+
+    /// cmp_ordered compares two maps as if they were lists of their elements
+    /// sorted by key bytes, comparing key bytes first and values second. The
+    /// result doesn't depend on capacity or layout, which makes it useful for
+    /// snapshot tests and canonicalization.
+    pub fn cmp_ordered(&self, other: &Self) -> Ordering
+    where
+        V: Ord,
+    {
+        let a = self.sorted_by_key_bytes().into_iter();
+        let b = other.sorted_by_key_bytes().into_iter();
+        a.map(|(k, v)| (k.as_byte(), v))
+            .cmp(b.map(|(k, v)| (k.as_byte(), v)))
+    }
+
+    /// sorted_keys returns all keys sorted by their bytes, e.g. for
+    /// deterministic reports.
+    pub fn sorted_keys(&self) -> Vec<&K> {
+        self.sorted_by_key_bytes()
+            .into_iter()
+            .map(|(k, _)| k)
+            .collect()
+    }
+
+    /// sorted_values_by_key returns all values in the order of their keys'
+    /// bytes, matching `sorted_keys`.
+    pub fn sorted_values_by_key(&self) -> Vec<&V> {
+        self.sorted_by_key_bytes()
+            .into_iter()
+            .map(|(_, v)| v)
+            .collect()
+    }
+
+    /// sorted_by_key_bytes returns all elements sorted by key bytes.
+    fn sorted_by_key_bytes(&self) -> Vec<(&K, &V)> {
+        let mut elems: Vec<_> = self.iter().collect();
+        elems.sort_unstable_by(|a, b| a.0.as_byte().cmp(b.0.as_byte()));
+        elems
+    }
+
+    /// keys_equal returns true if both maps contain the same set of keys,
+    /// regardless of their values. Keys are looked up in other by probing, so
+    /// the maps may differ in capacity and layout.
+    pub fn keys_equal<V2, S2>(&self, other: &HashMap<K, V2, S2>) -> bool
+    where
+        S2: BuildHasher,
+    {
+        self.len == other.len && self.keys().all(|k| other.index(k).is_some())
+    }
+
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn capacity(&self) -> u64 {
+        self.capacity
+    }
+
+    /// fingerprint returns a cheap summary of the keys in the map, combining
+    /// the stored key hashes and len independently of their order and of the
+    /// capacity. Two fingerprints differing means the key set changed. Values
+    /// don't contribute, so replacing or mutating a value goes unnoticed.
+    pub fn fingerprint(&self) -> u64 {
+        let (sum, xor) = self
+            .elems
+            .iter()
+            .flatten()
+            .fold((0_u64, 0_u64), |(sum, xor), e| {
+                (sum.wrapping_add(e.hash), xor ^ e.hash)
+            });
+        sum ^ xor.rotate_left(32) ^ self.len.wrapping_mul(0x9e37_79b9_7f4a_7c15)
+    }
+
+    /// is_capacity_power_of_two checks the invariant all slot arithmetic relies
+    /// on: the capacity is a power of 2 and matches both the slot array and
+    /// the mask.
+    pub fn is_capacity_power_of_two(&self) -> bool {
+        self.capacity.is_power_of_two()
+            && self.capacity == self.elems.len() as u64
+            && self.capacity == self.mask as u64 + 1
+    }
+
+    /// stats returns a snapshot of the size and health of the map, e.g. for
+    /// exporting metrics.
+    pub fn stats(&self) -> MapStats {
+        let (total_probe, max_probe) = self
+            .elems
+            .iter()
+            .flatten()
+            .fold((0, 0), |(total, max), e| (total + e.dist, max.max(e.dist)));
+        MapStats {
+            len: self.len,
+            capacity: self.capacity,
+            load_factor: self.load_factor,
+            threshold: self.threshold,
+            load: self.len as f64 / self.capacity as f64,
+            mean_probe: if self.len == 0 {
+                0.0
+            } else {
+                total_probe as f64 / self.len as f64
+            },
+            max_probe,
+            memory_footprint: mem::size_of::<Self>()
+                + self.elems.capacity() * mem::size_of::<Option<HashElem<K, V>>>()
+                + self.occupied.as_ref().map_or(0, |bits| bits.capacity() * 8),
+        }
+    }
+
+    /// capacity_remaining returns how many more elements fit within the load
+    /// factor threshold. The map grows on the first insert after it is
+    /// exceeded, so loaders can batch work up to that boundary.
+    pub fn capacity_remaining(&self) -> u64 {
+        self.threshold.saturating_sub(self.len)
+    }
+
+    /// recount_len sets len to the number of occupied slots, in case it
+    /// drifted, e.g. after `from_raw_slots` was given the wrong len.
+    pub fn recount_len(&mut self) {
+        self.len = self.elems.iter().flatten().count() as u64;
+    }
+
+    /// load_factor returns the percentage of slots that may be used before
+    /// the map grows.
+    pub fn load_factor(&self) -> usize {
+        self.load_factor
+    }
+
+    /// set_load_factor changes the load factor and recomputes the grow
+    /// threshold. If the map now holds more elements than the threshold
+    /// allows, it is rebuilt to a larger capacity immediately.
+    ///
+    /// Panics if load_factor is not within `1..100`.
+    pub fn set_load_factor(&mut self, load_factor: usize) {
+        check_load_factor(load_factor);
+
+        self.load_factor = load_factor;
+        self.threshold = (self.capacity * load_factor as u64) / 100;
+        if self.len > self.threshold {
+            self.resize(capacity_for(self.len, load_factor));
+        }
+    }
+
+    pub fn shrink_threshold(&self) -> Option<usize> {
+        self.shrink_threshold
+    }
+
+    pub fn max_probe_distance(&self) -> Option<u64> {
+        self.max_probe_distance
+    }
+
+    /// set_max_probe_distance makes inserting a new key grow the map if the
+    /// key, or any elem it displaces, would end up more than max slots away
+    /// from its home slot, even below the load factor threshold. `None`
+    /// disables the limit. This keeps probes short for clustered keys.
+    pub fn set_max_probe_distance(&mut self, max: Option<u64>) {
+        self.max_probe_distance = max;
+    }
+
+    pub fn growth_policy(&self) -> GrowthPolicy {
+        self.growth_policy
+    }
+
+    /// set_growth_policy sets how much the map grows when it runs out of
+    /// slots. Explicit resizes such as `reserve` ignore it.
+    ///
+    /// Panics if a factor is not a power of 2 within 2..=`MAX_GROWTH_FACTOR`.
+    pub fn set_growth_policy(&mut self, policy: GrowthPolicy) {
+        if let GrowthPolicy::Tiered {
+            small_factor,
+            large_factor,
+            ..
+        } = policy
+        {
+            for factor in [small_factor, large_factor] {
+                assert!(
+                    factor.is_power_of_two() && (2..=MAX_GROWTH_FACTOR).contains(&factor),
+                    "growth factor must be a power of 2 within 2..={}, got {}",
+                    MAX_GROWTH_FACTOR,
+                    factor
+                );
+            }
+        }
+        self.growth_policy = policy;
+    }
+
+    /// set_on_grow registers a hook that is called after the map grows because
+    /// it ran out of slots, e.g. to monitor resizes. Explicit resizes such as
+    /// `reserve` don't call it, and maps created from this one don't inherit
+    /// it. `None` removes the hook.
+    pub fn set_on_grow(&mut self, on_grow: Option<GrowHook>) {
+        self.on_grow = on_grow;
+    }
+
+    /// set_shrink_threshold makes `remove` call `shrink_to_fit` once fewer than
+    /// threshold percent of the slots are in use, or disables that if
+    /// threshold is `None`. The capacity only changes once the remaining
+    /// elements fit into fewer slots under the load factor, so thresholds above
+    /// half the load factor act like half the load factor.
+    ///
+    /// Panics if threshold is not within `1..100`.
+    pub fn set_shrink_threshold(&mut self, threshold: Option<usize>) {
+        if let Some(t) = threshold {
+            assert!(
+                t > 0 && t < 100,
+                "shrink threshold must be within 1..100, got {}",
+                t
+            );
+        }
+        self.shrink_threshold = threshold;
+    }
+}
+
+impl<K, S> HashMap<K, Vec<u8>, S>
+where
+    K: Eq + Hash + AsByte,
+    S: BuildHasher,
+{
+    /// get_bytes is like `get`, but returns the value as a byte slice.
+    pub fn get_bytes<Q>(&self, key: &Q) -> Option<&[u8]>
+    where
+        Q: ?Sized + Eq + Hash + AsByte,
+        K: Borrow<Q>,
+    {
+        self.get(key).map(Vec::as_slice)
+    }
+}
+
+#[cfg(any(test, feature = "testing"))]
+impl<K, V, S> HashMap<K, V, S>
+where
+    K: Eq + Hash + AsByte,
+    S: BuildHasher,
+{
+    /// assert_invariants panics if the internal layout of the map is
+    /// inconsistent: the capacity must be a power of 2 matching the mask and
+    /// the array, len must match the number of elems, and every elem must
+    /// store its actual probe distance and be reachable by lookup. An
+    /// occupancy bitmap must match the occupied slots.
+    pub fn assert_invariants(&self) {
+        assert!(
+            self.capacity.is_power_of_two(),
+            "capacity {} is not a power of 2",
+            self.capacity
+        );
+        assert_eq!(
+            self.capacity,
+            self.mask as u64 + 1,
+            "capacity doesn't match mask"
+        );
+        assert_eq!(
+            self.capacity,
+            self.elems.len() as u64,
+            "capacity doesn't match array"
+        );
+
+        let mut len = 0;
+        for (pos, e) in self.elems.iter().enumerate() {
+            let e = match e {
+                Some(e) => e,
+                None => continue,
+            };
+            len += 1;
+            assert_eq!(
+                distance(e.hash, pos, self.capacity),
+                e.dist,
+                "stored distance of slot {} is stale",
+                pos
+            );
+            assert_eq!(Some(pos), self.index(&e.key), "slot {} is unreachable", pos);
+        }
+        assert_eq!(len, self.len, "len doesn't match number of elems");
+
+        if let Some(bits) = &self.occupied {
+            assert_eq!(
+                occupancy::words(self.capacity),
+                bits.len(),
+                "occupancy bitmap doesn't match array"
+            );
+            for (pos, e) in self.elems.iter().enumerate() {
+                assert_eq!(
+                    e.is_some(),
+                    bits[pos / 64] & (1 << (pos % 64)) != 0,
+                    "occupancy of slot {} is stale",
+                    pos
+                );
+            }
+        }
+    }
+}
+
+impl<K, V, S> Debug for HashMap<K, V, S>
+where
+    K: Eq + Hash + AsByte + Debug,
+    V: Debug,
+    S: BuildHasher,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<K, V, S> Extend<(K, V)> for HashMap<K, V, S>
+where
+    K: Eq + Hash + AsByte,
+    S: BuildHasher,
+{
+    /// extend inserts all pairs of iter, reserving room for the lower bound of
+    /// its size hint up front so the map grows at most once for exact-size
+    /// iterators. (`Extend::extend_reserve` is still unstable and can't be
+    /// overridden.)
+    fn extend<T: IntoIterator<Item = (K, V)>>(&mut self, iter: T) {
+        let iter = iter.into_iter();
+        self.reserve(iter.size_hint().0);
+        for (k, v) in iter {
+            self.insert(k, v);
+        }
+    }
+}
+
+impl<K, V, S> FromIterator<(K, V)> for HashMap<K, V, S>
+where
+    K: Eq + Hash + AsByte,
+    S: BuildHasher + Default,
+{
+    /// from_iter sizes the map for the lower bound of the size hint of iter,
+    /// but never below `DEFAULT_CAPACITY`, so collecting an exact-size
+    /// iterator never grows the map.
+    fn from_iter<T: IntoIterator<Item = (K, V)>>(iter: T) -> Self {
+        let iter = iter.into_iter();
+        let capacity = capacity_for(iter.size_hint().0 as u64, 90) as usize;
+        let mut m =
+            Self::with_capacity_and_hasher(capacity.max(Self::DEFAULT_CAPACITY), S::default());
+        m.extend(iter);
+        m
+    }
+}
 
 // impl<K, V> HashMap<K, V>
 // where
@@ -271,169 +1862,2376 @@ where
 //     }
 // }
 
-pub struct Iter<'a, K: 'a, V: 'a>
-where
-    K: Eq + Hash + AsByte,
-{
-    map: &'a HashMap<K, V>,
-    at: usize,
-    num_found: usize,
-}
+pub struct Iter<'a, K: 'a, V: 'a, S = XxHash64Builder>
+where
+    K: Eq + Hash + AsByte,
+{
+    map: &'a HashMap<K, V, S>,
+    at: usize,
+    remaining: usize,
+}
+
+impl<'a, K: 'a, V: 'a, S> Iter<'a, K, V, S>
+where
+    K: Eq + Hash + AsByte,
+{
+    pub fn new(map: &'a HashMap<K, V, S>) -> Self {
+        Self {
+            map,
+            at: 0,
+            remaining: map.len as usize,
+        }
+    }
+}
+
+impl<'a, K, V, S> Iterator for Iter<'a, K, V, S>
+where
+    K: Eq + Hash + AsByte,
+{
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if self.remaining == 0 || self.at >= self.map.elems.len() {
+                return None;
+            }
+
+            let e = &self.map.elems[self.at];
+            self.at += 1;
+
+            if let Some(e) = e {
+                self.remaining -= 1;
+                return Some((&e.key, &e.value));
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<K, V, S> ExactSizeIterator for Iter<'_, K, V, S> where K: Eq + Hash + AsByte {}
+
+impl<K, V, S> FusedIterator for Iter<'_, K, V, S> where K: Eq + Hash + AsByte {}
+
+pub struct Keys<'a, K: 'a, V: 'a, S = XxHash64Builder>
+where
+    K: Eq + Hash + AsByte,
+{
+    inner: Iter<'a, K, V, S>,
+}
+
+impl<'a, K: 'a, V: 'a, S> Keys<'a, K, V, S>
+where
+    K: Eq + Hash + AsByte,
+{
+    pub fn new(inner: Iter<'a, K, V, S>) -> Self {
+        Self { inner }
+    }
+}
+
+impl<'a, K, V, S> Iterator for Keys<'a, K, V, S>
+where
+    K: Eq + Hash + AsByte,
+{
+    type Item = &'a K;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.inner.next() {
+            Some((k, _)) => Some(k),
+            None => None,
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<K, V, S> ExactSizeIterator for Keys<'_, K, V, S> where K: Eq + Hash + AsByte {}
+
+impl<K, V, S> FusedIterator for Keys<'_, K, V, S> where K: Eq + Hash + AsByte {}
+
+pub struct Values<'a, K: 'a, V: 'a, S = XxHash64Builder>
+where
+    K: Eq + Hash + AsByte,
+{
+    inner: Iter<'a, K, V, S>,
+}
+
+impl<'a, K: 'a, V: 'a, S> Values<'a, K, V, S>
+where
+    K: Eq + Hash + AsByte,
+{
+    pub fn new(inner: Iter<'a, K, V, S>) -> Self {
+        Self { inner }
+    }
+}
+
+impl<'a, K, V, S> Iterator for Values<'a, K, V, S>
+where
+    K: Eq + Hash + AsByte,
+{
+    type Item = &'a V;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|(_, v)| v)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<K, V, S> ExactSizeIterator for Values<'_, K, V, S> where K: Eq + Hash + AsByte {}
+
+impl<K, V, S> FusedIterator for Values<'_, K, V, S> where K: Eq + Hash + AsByte {}
+
+/// MapStats is a snapshot of the size and health of a map, returned by
+/// `HashMap::stats`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MapStats {
+    pub len: u64,
+    pub capacity: u64,
+    pub load_factor: usize,
+    /// threshold is the number of elements beyond which the map grows.
+    pub threshold: u64,
+    /// load is the fraction of slots in use.
+    pub load: f64,
+    /// mean_probe is the average distance of the elements from their home
+    /// slot.
+    pub mean_probe: f64,
+    pub max_probe: u64,
+    /// memory_footprint is the size in bytes of the map and its slot array,
+    /// not counting memory owned by the keys and values.
+    pub memory_footprint: usize,
+}
+
+/// MAX_GROWTH_FACTOR is the largest factor a `GrowthPolicy` may grow by.
+pub const MAX_GROWTH_FACTOR: u64 = 256;
+
+/// GrowthPolicy decides the capacity a map grows to when it runs out of slots.
+/// The slot index is the hash masked by the capacity, so the capacity must
+/// stay a power of 2 and every factor must be one too. Growing by less than
+/// double, e.g. 1.25x to limit peak memory, isn't possible.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum GrowthPolicy {
+    /// Double doubles the capacity.
+    #[default]
+    Double,
+    /// Tiered grows by small_factor while the slot array takes fewer than
+    /// cutoff_bytes, and by large_factor after that, e.g. to quadruple
+    /// small maps to get past their first grows quickly, but only double
+    /// large ones.
+    Tiered {
+        cutoff_bytes: usize,
+        small_factor: u64,
+        large_factor: u64,
+    },
+}
+
+/// InsertResult is returned by `HashMap::insert_reporting`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct InsertResult<V> {
+    /// previous is the value key had before, if any.
+    pub previous: Option<V>,
+    /// slot is the position of the elem, valid until the next mutation.
+    pub slot: usize,
+    /// probe_distance is how far slot is from the home slot of the key.
+    pub probe_distance: u64,
+}
+
+/// ProbeTooLong is returned by `HashMap::insert_bounded` if inserting would
+/// exceed the allowed probe distance.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ProbeTooLong {
+    /// probe is the probe distance the insert would have caused.
+    pub probe: u64,
+    pub max_probe: u64,
+}
+
+impl std::fmt::Display for ProbeTooLong {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "probe distance {} exceeds maximum of {}",
+            self.probe, self.max_probe
+        )
+    }
+}
+
+impl std::error::Error for ProbeTooLong {}
+
+/// CapacityTooLarge is returned by the `try_with_capacity` constructors if the
+/// requested capacity exceeds `MAX_CAPACITY`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CapacityTooLarge {
+    pub requested: u64,
+    pub max: u64,
+}
+
+impl std::fmt::Display for CapacityTooLarge {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "capacity {} exceeds maximum of {}",
+            self.requested, self.max
+        )
+    }
+}
+
+impl std::error::Error for CapacityTooLarge {}
+
+/// hash_key computes a hash of key. Hash is always non-zero.
+pub fn hash_key<K>(key: &K) -> u64
+where
+    K: Eq + Hash + AsByte + ?Sized,
+{
+    hash_key_with(&XxHash64Builder::default(), key)
+}
+
+/// hash_key_with computes a hash of key using hasher built by hash_builder.
+/// Hash is always non-zero.
+pub fn hash_key_with<S, K>(hash_builder: &S, key: &K) -> u64
+where
+    S: BuildHasher,
+    K: Eq + Hash + AsByte + ?Sized,
+{
+    let mut h = raw_hash_key_with(hash_builder, key);
+
+    if h == 0 {
+        h = 1;
+    } else {
+        let h_ptr = &h as *const u64 as *const i64;
+        unsafe {
+            if *h_ptr < 0 {
+                // influxdb, what the fuck!!! why change the hash value?
+                h = (0 - *h_ptr) as u64;
+            }
+        }
+    }
+
+    h
+}
+
+/// raw_hash_key_with computes a hash of key using hasher built by
+/// hash_builder, returning the hasher output verbatim. Unlike `hash_key_with`
+/// the hash may be zero.
+pub fn raw_hash_key_with<S, K>(hash_builder: &S, key: &K) -> u64
+where
+    S: BuildHasher,
+    K: Eq + Hash + AsByte + ?Sized,
+{
+    let mut hasher = hash_builder.build_hasher();
+    hasher.write(key.as_byte());
+    hasher.finish()
+}
+
+/// distance returns the probe distance for a hash in a slot index.
+/// NOTE: Capacity must be a power of 2.
+pub fn distance(hash: u64, i: usize, capacity: u64) -> u64 {
+    let mask = capacity - 1;
+    ((i as u64) + capacity - (hash & mask)) & mask
+}
+
+/// check_load_factor panics unless load_factor is within `1..100`. From 100
+/// on, the map would not grow before inserting into a full array.
+fn check_load_factor(load_factor: usize) {
+    assert!(
+        load_factor > 0 && load_factor < 100,
+        "load factor must be within 1..100, got {}",
+        load_factor
+    );
+}
+
+/// capacity_for returns the smallest power of 2 capacity whose threshold
+/// under load_factor holds len elements.
+fn capacity_for(len: u64, load_factor: usize) -> u64 {
+    checked_capacity_for(len, load_factor).expect("capacity overflow")
+}
+
+/// checked_capacity_for is like `capacity_for`, but returns `None` if the
+/// capacity overflows.
+fn checked_capacity_for(len: u64, load_factor: usize) -> Option<u64> {
+    let mut capacity = 2_u64;
+    while capacity.checked_mul(load_factor as u64)? / 100 < len {
+        capacity = capacity.checked_mul(2)?;
+    }
+    usize::try_from(capacity).ok().map(|_| capacity)
+}
+
+/// pow2 returns the number that is the next highest power of 2.
+/// Returns v if it is a power of 2, and an error if the result would exceed
+/// `MAX_CAPACITY`.
+fn pow2(v: u64) -> Result<u64, CapacityTooLarge> {
+    let mut i = 2_u64;
+    while i <= MAX_CAPACITY {
+        if i >= v {
+            return Ok(i);
+        }
+
+        i *= 2;
+    }
+
+    Err(CapacityTooLarge {
+        requested: v,
+        max: MAX_CAPACITY,
+    })
+}
+
+/// grown_capacity returns the capacity after growing capacity by factor. Both
+/// are powers of 2, and so is the product. Close to the maximum, it falls
+/// back to doubling.
+///
+/// Panics if even doubling would exceed `MAX_CAPACITY`.
+fn grown_capacity(capacity: u64, factor: u64) -> u64 {
+    match capacity.checked_mul(factor).filter(|&c| c <= MAX_CAPACITY) {
+        Some(c) => c,
+        None => match pow2(capacity * 2) {
+            Ok(c) => c,
+            Err(e) => panic!("{}", e),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+    use std::cmp::Ordering;
+    use std::hash::{BuildHasher, Hasher};
+    use std::sync::{Arc, Mutex};
+
+    use crate::{
+        grown_capacity, hash_key, raw_hash_key_with, AsByte, ByteKey, CapacityTooLarge, CharKey,
+        GrowthPolicy, HashElem, HashMap, InsertResult, ProbeTooLong, XxHash64Builder, MAX_CAPACITY,
+        MAX_GROWTH_FACTOR,
+    };
+
+    /// CollidingBuilder hashes every key to the same value, so every key has
+    /// the same home slot.
+    #[derive(Clone, Copy)]
+    struct CollidingBuilder(u64);
+
+    impl BuildHasher for CollidingBuilder {
+        type Hasher = CollidingHasher;
+
+        fn build_hasher(&self) -> Self::Hasher {
+            CollidingHasher(self.0)
+        }
+    }
+
+    struct CollidingHasher(u64);
+
+    impl Hasher for CollidingHasher {
+        fn finish(&self) -> u64 {
+            self.0
+        }
+
+        fn write(&mut self, _bytes: &[u8]) {}
+    }
+
+    /// SpreadBuilder hashes a decimal key i to 16 * i, so that keys cluster in
+    /// small maps and spread out as the map grows.
+    #[derive(Clone, Copy)]
+    struct SpreadBuilder;
+
+    impl BuildHasher for SpreadBuilder {
+        type Hasher = SpreadHasher;
+
+        fn build_hasher(&self) -> Self::Hasher {
+            SpreadHasher(0)
+        }
+    }
+
+    struct SpreadHasher(u64);
+
+    impl Hasher for SpreadHasher {
+        fn finish(&self) -> u64 {
+            self.0 * 16
+        }
+
+        fn write(&mut self, bytes: &[u8]) {
+            for b in bytes {
+                self.0 = self.0 * 10 + (b - b'0') as u64;
+            }
+        }
+    }
+
+    #[test]
+    fn test_hash() {
+        let n = hash_key("xyz");
+        assert_eq!(91681375387435871, n);
+    }
+
+    #[test]
+    fn test_raw_hash() {
+        // Reference values of XXH64 with seed 0.
+        let builder = XxHash64Builder::default();
+        assert_eq!(0xef46db3751d8e999, raw_hash_key_with(&builder, ""));
+        assert_eq!(0x44bc2cf5ad770999, raw_hash_key_with(&builder, "abc"));
+
+        // hash_key flips the sign of negative (as i64) hashes.
+        assert_eq!(0xef46db3751d8e999_u64.wrapping_neg(), hash_key(""));
+
+        let mut m = HashMap::with_raw_hash();
+        for i in 0..1000 {
+            m.insert(i.to_string(), i);
+        }
+        for i in 0..1000 {
+            assert_eq!(Some(&i), m.get(&i.to_string()));
+        }
+    }
+
+    #[test]
+    fn test_raw_hash_zero() {
+        let mut m = HashMap::with_raw_hasher(CollidingBuilder(0));
+        for i in 0..10 {
+            m.insert(i.to_string(), i);
+        }
+        assert_eq!(10, m.len());
+        for i in 0..10 {
+            assert_eq!(Some(&i), m.get(&i.to_string()));
+        }
+    }
+
+    #[test]
+    fn test_hash_map() {
+        let mut m = HashMap::new();
+
+        let size = 512;
+        for i in 0..size {
+            let key = i.to_string();
+            m.insert(key.clone(), key);
+        }
+        // m.print();
+
+        for i in 0..size {
+            let key = i.to_string();
+            let val = m.get(&key).unwrap();
+            assert_eq!(val.as_str(), key.as_str());
+        }
+
+        for (k, v) in m.iter_mut() {
+            println!("{} => {}", k, v);
+        }
+        for (k, v) in m.iter() {
+            println!("{} => {}", k, v);
+        }
+    }
+
+    #[test]
+    fn test_keys_equal() {
+        let mut a = HashMap::new();
+        let mut b = HashMap::with_capacity(1024);
+        for i in 0..100 {
+            a.insert(i.to_string(), i);
+            b.insert(i.to_string(), i.to_string());
+        }
+        assert!(a.keys_equal(&b));
+        assert!(b.keys_equal(&a));
+
+        b.insert("100".to_string(), "100".to_string());
+        assert!(!a.keys_equal(&b));
+
+        a.insert("101".to_string(), 101);
+        assert!(!a.keys_equal(&b));
+    }
+
+    #[test]
+    fn test_set_load_factor() {
+        let mut m = HashMap::new();
+        for i in 0..200 {
+            m.insert(i.to_string(), i);
+        }
+        assert_eq!(90, m.load_factor());
+        assert_eq!(256, m.capacity());
+
+        // A higher factor only moves the threshold.
+        m.set_load_factor(95);
+        assert_eq!(256, m.capacity());
+
+        // 200 elements no longer fit in 256 slots at 50%.
+        m.set_load_factor(50);
+        assert_eq!(50, m.load_factor());
+        assert_eq!(512, m.capacity());
+        for i in 0..200 {
+            assert_eq!(Some(&i), m.get(&i.to_string()));
+        }
+    }
+
+    #[test]
+    fn test_constructors_reject_invalid_load_factor() {
+        for load_factor in [0, 100, 150] {
+            let result = std::panic::catch_unwind(|| {
+                HashMap::<String, i32>::with_capacity_and_factor(16, load_factor)
+            });
+            assert!(result.is_err(), "load factor {} was accepted", load_factor);
+
+            let result = std::panic::catch_unwind(|| {
+                HashMap::<String, i32>::try_with_capacity_and_hasher_and_factor(
+                    16,
+                    XxHash64Builder::default(),
+                    load_factor,
+                )
+            });
+            assert!(result.is_err(), "load factor {} was accepted", load_factor);
+        }
+
+        assert_eq!(
+            1,
+            HashMap::<String, i32>::with_capacity_and_factor(16, 1).load_factor()
+        );
+        assert_eq!(
+            99,
+            HashMap::<String, i32>::with_capacity_and_factor(16, 99).load_factor()
+        );
+    }
+
+    #[test]
+    fn test_get_pair_mut() {
+        let mut m = HashMap::new();
+        for i in 0..100 {
+            m.insert(i.to_string(), i);
+        }
+
+        let (a, b) = m.get_pair_mut("1", "2").unwrap();
+        std::mem::swap(a, b);
+        assert_eq!(Some(&2), m.get("1"));
+        assert_eq!(Some(&1), m.get("2"));
+
+        let (a, b) = m.get_pair_mut("3", "4").unwrap();
+        *a += 10;
+        *b += 20;
+        assert_eq!(Some(&13), m.get("3"));
+        assert_eq!(Some(&24), m.get("4"));
+    }
+
+    #[test]
+    fn test_get_pair_mut_rejects() {
+        let mut m = HashMap::new();
+        m.insert("a".to_string(), 1);
+        m.insert("b".to_string(), 2);
+
+        assert!(m.get_pair_mut("a", "a").is_none());
+        assert!(m.get_pair_mut("a", "c").is_none());
+        assert!(m.get_pair_mut("c", "b").is_none());
+    }
+
+    #[test]
+    fn test_from_pairs() {
+        let m = HashMap::from_pairs(&[("a".to_string(), 1), ("b".to_string(), 2)]);
+        assert_eq!(2, m.len());
+        assert_eq!(Some(&1), m.get("a"));
+        assert_eq!(Some(&2), m.get("b"));
+        assert_eq!(None, m.get("c"));
+
+        let pairs: Vec<_> = (0..1000).map(|i| (i.to_string(), i)).collect();
+        let m = HashMap::from_pairs(&pairs);
+        assert_eq!(2048, m.capacity());
+        for (k, v) in &pairs {
+            assert_eq!(Some(v), m.get(k));
+        }
+    }
+
+    #[test]
+    fn test_insert() {
+        let mut m = HashMap::new();
+        assert_eq!(None, m.insert("a".to_string(), 1));
+        assert_eq!(Some(1), m.insert("a".to_string(), 2));
+        assert_eq!(Some(&2), m.get("a"));
+        assert_eq!(1, m.len());
+    }
+
+    #[test]
+    fn test_insert_bounded() {
+        let mut m = HashMap::with_hasher(CollidingBuilder(7));
+        for i in 0..4 {
+            assert_eq!(Ok(None), m.insert_bounded(i.to_string(), i, 3));
+        }
+
+        // The fifth colliding key would sit 4 slots away from home.
+        let err = m.insert_bounded("4".to_string(), 4, 3).unwrap_err();
+        assert_eq!(
+            ProbeTooLong {
+                probe: 4,
+                max_probe: 3
+            },
+            err
+        );
+        assert_eq!(4, m.len());
+        assert_eq!(None, m.get("4"));
+
+        // Overwriting never probes further than the existing key.
+        assert_eq!(Ok(Some(3)), m.insert_bounded("3".to_string(), 30, 0));
+        assert_eq!(Some(&30), m.get("3"));
+    }
+
+    #[test]
+    fn test_insert_bounded_leaves_map_alone_on_error() {
+        let mut m = HashMap::with_capacity_and_hasher(16, CollidingBuilder(0));
+        for i in 0..15 {
+            m.insert(i.to_string(), i);
+        }
+        // Past the threshold of 14, so the next insert would grow the map.
+        assert_eq!(16, m.capacity());
+        let layout = |m: &HashMap<String, i32, CollidingBuilder>| -> Vec<_> {
+            m.raw_slots()
+                .map(|s| s.map(|s| (s.hash, s.dist, s.key.clone())))
+                .collect()
+        };
+        let before = layout(&m);
+
+        assert!(m.insert_bounded("15".to_string(), 15, 3).is_err());
+        assert_eq!(16, m.capacity());
+        assert_eq!(before, layout(&m));
+
+        assert_eq!(Ok(None), m.insert_bounded("15".to_string(), 15, 15));
+        assert_eq!(32, m.capacity());
+        m.assert_invariants();
+        for i in 0..16 {
+            assert_eq!(Some(&i), m.get(&i.to_string()));
+        }
+
+        // A map without a free slot has to grow before it can be probed.
+        let mut m = HashMap::with_capacity_and_hasher(2, CollidingBuilder(0));
+        m.insert("a".to_string(), 0);
+        m.insert("b".to_string(), 1);
+        assert_eq!(2, m.len());
+        assert_eq!(2, m.capacity());
+        assert_eq!(Ok(None), m.insert_bounded("c".to_string(), 2, 2));
+        assert_eq!(4, m.capacity());
+    }
+
+    #[test]
+    fn test_iter_size_hint() {
+        let mut m = HashMap::new();
+        for i in 0..100 {
+            m.insert(i.to_string(), i);
+        }
+
+        let mut iter = m.iter().peekable();
+        let mut remaining = 100;
+        loop {
+            assert_eq!((remaining, Some(remaining)), iter.size_hint());
+            assert_eq!(remaining, iter.len());
+            if iter.next().is_none() {
+                break;
+            }
+            remaining -= 1;
+        }
+        assert_eq!(0, remaining);
+    }
+
+    #[test]
+    fn test_clear() {
+        let mut m = HashMap::new();
+        for i in 0..1000 {
+            m.insert(i.to_string(), i);
+        }
+        let capacity = m.capacity();
+
+        m.clear();
+        assert_eq!(0, m.len());
+        assert_eq!(capacity, m.capacity());
+        assert_eq!(None, m.get("1"));
+        assert_eq!(0, m.iter().count());
+
+        m.insert("1".to_string(), 1);
+        assert_eq!(Some(&1), m.get("1"));
+    }
+
+    #[test]
+    fn test_clear_and_shrink() {
+        let mut m = HashMap::new();
+        for i in 0..1000 {
+            m.insert(i.to_string(), i);
+        }
+
+        m.clear_and_shrink();
+        assert_eq!(0, m.len());
+        assert_eq!(256, m.capacity());
+        assert_eq!(None, m.get("1"));
+
+        for i in 0..1000 {
+            m.insert(i.to_string(), i);
+        }
+        for i in 0..1000 {
+            assert_eq!(Some(&i), m.get(&i.to_string()));
+        }
+    }
+
+    #[test]
+    fn test_clear_and_shrink_small() {
+        let mut m = HashMap::new_small();
+        for i in 0..5 {
+            m.insert(i.to_string(), i);
+        }
+
+        m.clear_and_shrink();
+        assert_eq!(0, m.len());
+        assert_eq!(HashMap::<String, i32>::SMALL_CAPACITY as u64, m.capacity());
+        assert_eq!(None, m.get("1"));
+    }
+
+    #[test]
+    fn test_unit_values() {
+        let mut m: HashMap<String, ()> = HashMap::new();
+        for i in 0..500 {
+            assert_eq!(None, m.insert(i.to_string(), ()));
+        }
+        assert_eq!(Some(()), m.insert("1".to_string(), ()));
+        assert_eq!(500, m.len());
+        assert!(m.get("1").is_some());
+        assert!(m.get("500").is_none());
+
+        m.retain(|k, _| k.len() < 3);
+        assert_eq!(100, m.len());
+    }
+
+    #[test]
+    fn test_unit_values_layout() {
+        use crate::HashElem;
+        use std::mem::size_of;
+
+        // A unit value takes no space, and the key's niche keeps the Option
+        // free.
+        assert_eq!(
+            size_of::<String>() + 2 * size_of::<u64>(),
+            size_of::<HashElem<String, ()>>()
+        );
+        assert_eq!(
+            size_of::<HashElem<String, ()>>(),
+            size_of::<Option<HashElem<String, ()>>>()
+        );
+    }
+
+    #[test]
+    fn test_reserve() {
+        let mut m = HashMap::new();
+        m.insert("a".to_string(), 0);
+
+        m.reserve(1000);
+        assert_eq!(2048, m.capacity());
+        assert_eq!(Some(&0), m.get("a"));
+
+        // Enough room already.
+        m.reserve(10);
+        assert_eq!(2048, m.capacity());
+    }
+
+    #[test]
+    #[should_panic(expected = "capacity overflow")]
+    fn test_reserve_overflow() {
+        let mut m = HashMap::new();
+        m.insert("a".to_string(), 1);
+        m.reserve(usize::MAX);
+    }
+
+    #[test]
+    fn test_reserve_high_load_factor() {
+        let mut m = HashMap::with_capacity_and_factor(16, 90);
+        for i in 0..10 {
+            m.insert(i.to_string(), i);
+        }
+
+        // 1024 slots would hold 1010 elems, but only 921 at 90%.
+        m.reserve(1000);
+        assert_eq!(2048, m.capacity());
+        assert!(m.threshold >= 1010);
+
+        let grows = Arc::new(Mutex::new(0));
+        let count = grows.clone();
+        m.set_on_grow(Some(Box::new(move |_, _| *count.lock().unwrap() += 1)));
+        for i in 10..1010 {
+            m.insert(i.to_string(), i);
+        }
+        assert_eq!(0, *grows.lock().unwrap());
+        assert_eq!(1010, m.len());
+    }
+
+    #[test]
+    fn test_reserve_exact() {
+        for additional in [0, 1, 230, 231, 1000, 3686, 3687] {
+            let mut m: HashMap<String, i32> = HashMap::new();
+            m.reserve_exact(additional);
+
+            // The exact capacity is the smallest sufficient power of 2.
+            assert!(m.capacity().is_power_of_two());
+            assert!(m.capacity() * 90 / 100 >= additional as u64);
+            if m.capacity() > 256 {
+                assert!(m.capacity() / 2 * 90 / 100 < additional as u64);
+            }
+
+            // There is no headroom for reserve_exact to leave out.
+            let mut r: HashMap<String, i32> = HashMap::new();
+            r.reserve(additional);
+            assert_eq!(r.capacity(), m.capacity(), "additional {}", additional);
+        }
+    }
+
+    #[test]
+    fn test_try_reserve() {
+        let mut m = HashMap::new();
+        m.insert("a".to_string(), 0);
+
+        assert!(m.try_reserve(1000).is_ok());
+        assert_eq!(2048, m.capacity());
+        assert_eq!(Some(&0), m.get("a"));
+
+        assert!(m.try_reserve(usize::MAX).is_err());
+        assert_eq!(2048, m.capacity());
+        assert_eq!(Some(&0), m.get("a"));
+    }
+
+    #[test]
+    fn test_extend() {
+        let mut m = HashMap::new();
+        m.extend((0..50_000).map(|i| (i.to_string(), i)));
+
+        // Reserved once for all 50_000 elements: 50_000 / 0.9 rounds up to 2^16.
+        assert_eq!(50_000, m.len());
+        assert_eq!(65536, m.capacity());
+        for i in 0..50_000 {
+            assert_eq!(Some(&i), m.get(&i.to_string()));
+        }
+    }
+
+    #[test]
+    fn test_min_max_key_value() {
+        let mut m = HashMap::new();
+        assert_eq!(None, m.min_key_value());
+        assert_eq!(None, m.max_key_value());
+
+        for k in ["mem", "cpu", "disk", "net", "cpu0"] {
+            m.insert(k.to_string(), k.len());
+        }
+        assert_eq!(Some((&"cpu".to_string(), &3)), m.min_key_value());
+        assert_eq!(Some((&"net".to_string(), &3)), m.max_key_value());
+    }
+
+    #[test]
+    fn test_into_vec() {
+        let mut m = HashMap::new();
+        for i in 0..1000 {
+            m.insert(i.to_string(), i);
+        }
+
+        let mut v = m.into_vec();
+        assert_eq!(1000, v.len());
+
+        v.sort_by_key(|(_, v)| *v);
+        for (i, (k, v)) in v.into_iter().enumerate() {
+            assert_eq!(i.to_string(), k);
+            assert_eq!(i, v);
+        }
+    }
+
+    #[test]
+    fn test_with_capacity_and_hasher_and_factor() {
+        let mut m = HashMap::with_capacity_and_hasher_and_factor(64, CollidingBuilder(7), 50);
+        assert_eq!(64, m.capacity());
+        assert_eq!(50, m.load_factor());
+
+        // The threshold is 32 at 50%, so the insert after exceeding it grows.
+        for i in 0..33 {
+            m.insert(i.to_string(), i);
+        }
+        assert_eq!(64, m.capacity());
+        m.insert("33".to_string(), 33);
+        assert_eq!(128, m.capacity());
+
+        // All keys collide, so they sit one after the other.
+        let mut dists: Vec<u64> = Vec::new();
+        m.retain_with_meta(|_, _, dist| {
+            dists.push(dist);
+            true
+        });
+        dists.sort();
+        assert_eq!((0..34).collect::<Vec<u64>>(), dists);
+    }
+
+    #[test]
+    fn test_normalizer() {
+        fn lowercase(b: &[u8]) -> Cow<'_, [u8]> {
+            if b.iter().any(u8::is_ascii_uppercase) {
+                Cow::Owned(b.to_ascii_lowercase())
+            } else {
+                Cow::Borrowed(b)
+            }
+        }
+
+        let mut m = HashMap::with_normalizer(lowercase);
+        assert_eq!(None, m.insert("ABC".to_string(), 1));
+        assert_eq!(Some(1), m.insert("abc".to_string(), 2));
+        assert_eq!(1, m.len());
+        assert_eq!(Some(&2), m.get("aBc"));
+        assert_eq!(None, m.get("abd"));
+
+        *m.entry("Abc".to_string()).or_insert(0) += 1;
+        assert_eq!(Some(&3), m.get("ABC"));
+
+        for i in 0..1000 {
+            m.insert(format!("KEY{}", i), i);
+        }
+        for i in 0..1000 {
+            assert_eq!(Some(&i), m.get(&format!("key{}", i)));
+        }
+    }
+
+    #[test]
+    fn test_map_values_in_place() {
+        let mut m = HashMap::new();
+        for i in 0..100 {
+            m.insert(i.to_string(), i);
+        }
+
+        m.map_values_in_place(|v| *v *= 2);
+        for i in 0..100 {
+            assert_eq!(Some(&(i * 2)), m.get(&i.to_string()));
+        }
+    }
+
+    #[test]
+    fn test_remove_if() {
+        let mut m = HashMap::with_hasher(CollidingBuilder(3));
+        for i in 0..5 {
+            m.insert(i.to_string(), i);
+        }
+
+        assert_eq!(None, m.remove_if("2", |v| *v > 2));
+        assert_eq!(Some(&2), m.get("2"));
+        assert_eq!(5, m.len());
+
+        assert_eq!(Some(2), m.remove_if("2", |v| *v == 2));
+        assert_eq!(None, m.get("2"));
+        assert_eq!(4, m.len());
+        m.assert_invariants();
+
+        let mut called = false;
+        assert_eq!(
+            None,
+            m.remove_if("9", |_| {
+                called = true;
+                true
+            })
+        );
+        assert!(!called);
+        assert_eq!(4, m.len());
+    }
+
+    #[test]
+    fn test_insert_keeps_distances() {
+        let mut m = HashMap::new();
+        for i in 0..1000 {
+            m.insert(i.to_string(), i);
+        }
+
+        // Elems displaced by a Robin Hood swap must carry their own distance.
+        for (pos, e) in m.elems.iter().enumerate() {
+            if let Some(e) = e {
+                assert_eq!(crate::distance(e.hash, pos, m.capacity), e.dist);
+            }
+        }
+    }
+
+    #[test]
+    fn test_remove() {
+        let mut m = HashMap::new();
+        for i in 0..1000 {
+            m.insert(i.to_string(), i);
+        }
+
+        for i in (0..1000).step_by(3) {
+            assert_eq!(Some(i), m.remove(&i.to_string()));
+        }
+        assert_eq!(None, m.remove("0"));
+        assert_eq!(666, m.len());
+        for i in 0..1000 {
+            assert_eq!(i % 3 != 0, m.get(&i.to_string()).is_some());
+        }
+    }
+
+    #[test]
+    fn test_remove_colliding() {
+        let mut m = HashMap::with_hasher(CollidingBuilder(7));
+        for i in 0..10 {
+            m.insert(i.to_string(), i);
+        }
+
+        // Removing the head of the chain shifts every other key back.
+        assert_eq!(Some(0), m.remove("0"));
+        let mut dists = Vec::new();
+        m.retain_with_meta(|_, v, dist| {
+            dists.push((*v, dist));
+            true
+        });
+        dists.sort();
+        assert_eq!(
+            (1..10).map(|i| (i, i as u64 - 1)).collect::<Vec<_>>(),
+            dists
+        );
+        for i in 1..10 {
+            assert_eq!(Some(&i), m.get(&i.to_string()));
+        }
+    }
+
+    #[test]
+    fn test_swap_values() {
+        let mut m = HashMap::new();
+        for i in 0..100 {
+            m.insert(i.to_string(), i);
+        }
+        let capacity = m.capacity();
+
+        assert!(m.swap_values("1", "2"));
+        assert_eq!(Some(&2), m.get("1"));
+        assert_eq!(Some(&1), m.get("2"));
+
+        assert!(m.swap_values("3", "3"));
+        assert_eq!(Some(&3), m.get("3"));
+
+        assert!(!m.swap_values("1", "100"));
+        assert_eq!(Some(&2), m.get("1"));
+
+        assert_eq!(100, m.len());
+        assert_eq!(capacity, m.capacity());
+        let mut keys: Vec<_> = m.keys().map(|k| k.parse::<i32>().unwrap()).collect();
+        keys.sort();
+        assert_eq!((0..100).collect::<Vec<_>>(), keys);
+    }
+
+    #[test]
+    fn test_entries_within_distance() {
+        let mut m = HashMap::with_hasher(CollidingBuilder(7));
+        for i in 0..10 {
+            m.insert(i.to_string(), i);
+        }
+
+        // Only the first colliding key sits at its home slot.
+        let home: Vec<_> = m.entries_within_distance(0).collect();
+        assert_eq!(vec![(&"0".to_string(), &0)], home);
+        assert_eq!(3, m.entries_within_distance(2).count());
+        assert_eq!(7, m.entries_beyond_distance(2).count());
+
+        let mut m = HashMap::new();
+        for i in 0..200 {
+            m.insert(i.to_string(), i);
+        }
+        for (k, _) in m.entries_within_distance(0) {
+            assert_eq!(m.index(k), Some((hash_key(k) & m.mask as u64) as usize));
+        }
+        let within = m.entries_within_distance(1).count();
+        let beyond = m.entries_beyond_distance(1).count();
+        assert_eq!(200, within + beyond);
+    }
+
+    #[test]
+    fn test_to_arc_entries() {
+        let mut m = HashMap::new();
+        for i in 0..100 {
+            m.insert(i.to_string(), i);
+        }
+
+        let snapshot = m.to_arc_entries();
+        let shared = snapshot.clone();
+        m.clear();
+
+        assert_eq!(100, snapshot.len());
+        assert_eq!(&snapshot[..], &shared[..]);
+        for (k, v) in shared.iter() {
+            assert_eq!(&v.to_string(), k);
+        }
+    }
+
+    #[test]
+    fn test_remove_returning_shifted() {
+        let mut m = HashMap::with_hasher(CollidingBuilder(7));
+        for i in 0..5 {
+            m.insert(i.to_string(), i);
+        }
+        // The chain occupies slots 7..12, removing slot 8 shifts 9..12 back.
+        let (v, shifted) = m.remove_returning_shifted("1").unwrap();
+        assert_eq!(1, v);
+        assert_eq!(vec![9, 10, 11], shifted);
+
+        // The tail of the chain has nothing behind it.
+        let (v, shifted) = m.remove_returning_shifted("4").unwrap();
+        assert_eq!(4, v);
+        assert!(shifted.is_empty());
+        assert_eq!(None, m.remove_returning_shifted("4"));
+    }
+
+    #[test]
+    fn test_iter_owned() {
+        let mut m = HashMap::new();
+        for i in 0..100 {
+            m.insert(i.to_string(), i);
+        }
+
+        let owned: Vec<(String, i32)> = m.iter_owned().collect();
+        m.clear();
+
+        let handle = std::thread::spawn(move || owned.len());
+        assert_eq!(100, handle.join().unwrap());
+    }
+
+    #[test]
+    fn test_invariants_random_ops() {
+        // xorshift64, seeded for reproducibility.
+        let mut state = 0x2545f4914f6cdd1d_u64;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+
+        let mut m = HashMap::with_capacity(16);
+        let mut model = std::collections::HashMap::new();
+        for _ in 0..5000 {
+            let key = (next() % 300).to_string();
+            match next() % 4 {
+                0 | 1 => assert_eq!(model.insert(key.clone(), 1), m.insert(key, 1)),
+                2 => assert_eq!(model.remove(&key), m.remove(&key)),
+                _ => {
+                    *model.entry(key.clone()).or_insert(0) += 1;
+                    *m.entry(key).or_insert(0) += 1;
+                }
+            }
+            m.assert_invariants();
+            assert_eq!(model.len() as u64, m.len());
+        }
+        for (k, v) in &model {
+            assert_eq!(Some(v), m.get(k));
+        }
+    }
+
+    #[test]
+    fn test_get_cloned() {
+        let mut m = HashMap::new();
+        m.insert("a".to_string(), vec![1, 2]);
+
+        let mut v = m.get_cloned("a").unwrap();
+        v.push(3);
+        m.get_mut("a").unwrap().push(4);
+        assert_eq!(vec![1, 2, 3], v);
+        assert_eq!(Some(&vec![1, 2, 4]), m.get("a"));
+        assert_eq!(None, m.get_cloned("b"));
+    }
+
+    #[test]
+    fn test_get_cloned_or_default() {
+        let mut m = HashMap::new();
+        m.insert("a".to_string(), vec![1, 2]);
+
+        assert_eq!(vec![1, 2], m.get_cloned_or_default("a"));
+        assert_eq!(Vec::<i32>::new(), m.get_cloned_or_default("b"));
+        assert_eq!(1, m.len());
+    }
+
+    #[test]
+    fn test_get_with_probes() {
+        let mut m = HashMap::with_hasher(CollidingBuilder(7));
+        for i in 0..10 {
+            m.insert(i.to_string(), i);
+        }
+
+        // Each key of the chain sits at distance i from the shared home slot.
+        for i in 0..10 {
+            assert_eq!((Some(&i), i as u64 + 1), m.get_with_probes(&i.to_string()));
+        }
+        // A miss walks the whole chain and stops at the empty slot after it.
+        assert_eq!((None, 11), m.get_with_probes("10"));
+    }
+
+    #[test]
+    fn test_from_sorted_pairs() {
+        let pairs: Vec<_> = (0..1000).map(|i| (format!("{:04}", i), i)).collect();
+        let m = HashMap::from_sorted_pairs(pairs.clone());
+        assert_eq!(1000, m.len());
+        assert_eq!(2048, m.capacity());
+        for (k, v) in &pairs {
+            assert_eq!(Some(v), m.get(k));
+        }
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "duplicate key")]
+    fn test_from_sorted_pairs_duplicate() {
+        HashMap::from_sorted_pairs(vec![("a".to_string(), 1), ("a".to_string(), 2)]);
+    }
+
+    #[test]
+    fn test_count_distinct() {
+        let words = "the quick brown fox jumps over the lazy dog the end";
+        let n = HashMap::count_distinct(words.split(' ').map(str::to_string));
+        assert_eq!(9, n);
+
+        assert_eq!(0, HashMap::count_distinct(Vec::<String>::new()));
+    }
+
+    #[test]
+    fn test_grow_after_removals() {
+        let mut m = HashMap::with_capacity(16);
+        for i in 0..14 {
+            m.insert(i.to_string(), i);
+        }
+        for i in (0..14).step_by(2) {
+            assert_eq!(Some(i), m.remove(&i.to_string()));
+        }
+
+        // Refill past the threshold to force a grow.
+        for i in 14..30 {
+            m.insert(i.to_string(), i);
+        }
+        assert!(m.capacity() > 16);
+        m.assert_invariants();
+
+        let live: Vec<_> = (0..30).filter(|i| i >= &14 || i % 2 == 1).collect();
+        assert_eq!(live.len() as u64, m.len());
+        assert_eq!(live.len(), m.iter().count());
+        for i in 0..14 {
+            let (v, _) = m.get_with_probes(&i.to_string());
+            assert_eq!(if i % 2 == 1 { Some(&i) } else { None }, v);
+        }
+    }
+
+    #[test]
+    fn test_keys_values_len() {
+        let mut m = HashMap::new();
+        for i in 0..100 {
+            m.insert(i.to_string(), i);
+        }
+        m.remove("0");
+
+        assert_eq!(m.len(), m.values().len() as u64);
+        assert_eq!(m.len(), m.keys().len() as u64);
+        assert_eq!((1..100).sum::<i32>(), m.values().sum::<i32>());
+
+        let mut keys = m.keys();
+        keys.next();
+        assert_eq!(98, keys.len());
+        keys.by_ref().for_each(drop);
+        assert_eq!(0, keys.len());
+        assert_eq!(None, keys.next());
+    }
+
+    #[test]
+    fn test_try_with_capacity() {
+        let m = HashMap::<String, u32>::try_with_capacity(16).unwrap();
+        assert_eq!(16, m.capacity());
+
+        let err = HashMap::<String, u32>::try_with_capacity(MAX_CAPACITY as usize + 1).unwrap_err();
+        assert_eq!(
+            CapacityTooLarge {
+                requested: MAX_CAPACITY + 1,
+                max: MAX_CAPACITY,
+            },
+            err
+        );
+        assert_eq!(
+            format!(
+                "capacity {} exceeds maximum of {}",
+                MAX_CAPACITY + 1,
+                MAX_CAPACITY
+            ),
+            err.to_string()
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "exceeds maximum of 2305843009213693952")]
+    fn test_with_capacity_too_large() {
+        HashMap::<String, u32>::with_capacity(usize::MAX);
+    }
+
+    #[test]
+    fn test_shrink_threshold() {
+        let mut half = HashMap::with_capacity(64);
+        half.set_shrink_threshold(Some(50));
+        let mut quarter = HashMap::with_capacity(64);
+        quarter.set_shrink_threshold(Some(25));
+        for i in 0..40 {
+            half.insert(i.to_string(), i);
+            quarter.insert(i.to_string(), i);
+        }
+
+        // 28 elems fit into 32 slots, and the map is less than half full.
+        for i in 28..40 {
+            half.remove(&i.to_string());
+            quarter.remove(&i.to_string());
+        }
+        assert_eq!(32, half.capacity());
+        assert_eq!(64, quarter.capacity());
+
+        for i in 15..28 {
+            quarter.remove(&i.to_string());
+        }
+        assert_eq!(32, quarter.capacity());
+        for i in 0..15 {
+            assert_eq!(Some(&i), quarter.get(&i.to_string()));
+        }
+
+        // Without a threshold, removal never shrinks.
+        let mut m = HashMap::with_capacity(64);
+        for i in 0..40 {
+            m.insert(i.to_string(), i);
+        }
+        for i in 0..40 {
+            m.remove(&i.to_string());
+        }
+        assert_eq!(64, m.capacity());
+    }
+
+    #[test]
+    fn test_get_bytes() {
+        let mut m = HashMap::new();
+        m.insert("a".to_string(), b"alpha".to_vec());
+        m.insert("b".to_string(), Vec::new());
+
+        assert_eq!(Some(b"alpha".as_slice()), m.get_bytes("a"));
+        assert_eq!(Some([].as_slice()), m.get_bytes("b"));
+        assert_eq!(None, m.get_bytes("c"));
+    }
+
+    #[test]
+    fn test_max_probe_distance() {
+        let max_dist = |m: &HashMap<String, u64, SpreadBuilder>| {
+            m.keys().map(|k| m.get_with_probes(k).1 - 1).max().unwrap()
+        };
+
+        let mut m = HashMap::with_capacity_and_hasher(64, SpreadBuilder);
+        for i in 1..=32 {
+            m.insert(i.to_string(), i);
+        }
+        assert_eq!(64, m.capacity());
+        let unbounded = max_dist(&m);
+
+        let mut m = HashMap::with_capacity_and_hasher(64, SpreadBuilder);
+        m.set_max_probe_distance(Some(3));
+        for i in 1..=32 {
+            m.insert(i.to_string(), i);
+        }
+        assert!(m.capacity() > 64);
+        assert!(max_dist(&m) <= 3 && max_dist(&m) < unbounded);
+        for i in 1..=32 {
+            assert_eq!(Some(&i), m.get(&i.to_string()));
+        }
+        m.assert_invariants();
+
+        // Keys that always collide don't grow the map without bound.
+        let mut m = HashMap::with_capacity_and_hasher(16, CollidingBuilder(7));
+        m.set_max_probe_distance(Some(2));
+        for i in 0..100 {
+            m.insert(i.to_string(), i);
+        }
+        assert!(m.capacity() <= 512);
+        assert_eq!(100, m.len());
+    }
+
+    #[test]
+    fn test_retain_mut() {
+        let mut m = HashMap::new();
+        for i in 0..100 {
+            m.insert(i.to_string(), i);
+        }
+
+        m.retain_mut(|_, v| {
+            *v += 10;
+            *v <= 50
+        });
+        assert_eq!(41, m.len());
+        for i in 0..100 {
+            let expected = if i + 10 <= 50 { Some(i + 10) } else { None };
+            assert_eq!(expected.as_ref(), m.get(&i.to_string()));
+        }
+    }
+
+    #[test]
+    fn test_capacity_remaining() {
+        let mut m = HashMap::with_capacity(16);
+        assert_eq!(14, m.capacity_remaining());
+        for i in 0..14 {
+            m.insert(i.to_string(), i);
+            assert_eq!(13 - i as u64, m.capacity_remaining());
+        }
+
+        m.insert("14".to_string(), 14);
+        assert_eq!(0, m.capacity_remaining());
+        assert_eq!(16, m.capacity());
+
+        // The next insert grows the map and frees up room again.
+        m.insert("15".to_string(), 15);
+        assert_eq!(32, m.capacity());
+        assert_eq!(28 - 16, m.capacity_remaining());
+    }
+
+    #[test]
+    fn test_index_home_slot() {
+        let mut m = HashMap::with_hasher(CollidingBuilder(7));
+        for i in 0..4 {
+            m.insert(i.to_string(), i);
+        }
+
+        // "0" is found in its home slot, the others by probing past it.
+        assert_eq!(Some(7), m.index("0"));
+        for i in 0..4 {
+            assert_eq!(Some(7 + i as usize), m.index(&i.to_string()));
+            assert_eq!(Some(&i), m.get(&i.to_string()));
+        }
+        assert_eq!(None, m.index("4"));
+
+        let mut m = HashMap::new();
+        for i in 0..1000 {
+            m.insert(i.to_string(), i);
+        }
+        let home = m.keys().filter(|k| m.get_with_probes(*k).1 == 1).count();
+        assert!(home > 0);
+        for i in 0..1000 {
+            assert_eq!(Some(&i), m.get(&i.to_string()));
+        }
+        assert_eq!(None, m.get("1000"));
+    }
+
+    #[test]
+    fn test_compute_if_present() {
+        let mut m = HashMap::new();
+        m.insert("a".to_string(), 1);
+        m.insert("b".to_string(), 2);
+
+        // Modify and keep.
+        let v = m.compute_if_present("a", |k, v| {
+            assert_eq!("a", k);
+            *v += 10;
+            true
+        });
+        assert_eq!(Some(&mut 11), v);
+        assert_eq!(Some(&11), m.get("a"));
+
+        // Modify and remove.
+        let v = m.compute_if_present("b", |_, v| {
+            *v += 10;
+            false
+        });
+        assert_eq!(None, v);
+        assert_eq!(None, m.get("b"));
+        assert_eq!(1, m.len());
+
+        // f isn't called for a missing key.
+        assert_eq!(None, m.compute_if_present("c", |_, _| unreachable!()));
+    }
+
+    #[test]
+    fn test_compute() {
+        let mut m = HashMap::new();
+
+        // Absent to set.
+        m.compute("a".to_string(), |v| {
+            assert_eq!(None, v);
+            Some(1)
+        });
+        assert_eq!(Some(&1), m.get("a"));
+
+        // Present to updated.
+        m.compute("a".to_string(), |v| v.map(|v| v + 10));
+        assert_eq!(Some(&11), m.get("a"));
+        assert_eq!(1, m.len());
+
+        // Present to removed.
+        m.compute("a".to_string(), |v| {
+            assert_eq!(Some(11), v);
+            None
+        });
+        assert_eq!(None, m.get("a"));
+        assert_eq!(0, m.len());
+
+        // Absent stays absent.
+        m.compute("b".to_string(), |v| v);
+        assert_eq!(None, m.get("b"));
+        assert_eq!(0, m.len());
+    }
+
+    #[test]
+    fn test_fingerprint() {
+        let mut m = HashMap::with_capacity(16);
+        let empty = m.fingerprint();
+        for i in 0..10 {
+            m.insert(i.to_string(), i);
+        }
+        let fp = m.fingerprint();
+        assert_ne!(empty, fp);
+
+        // Values don't contribute.
+        *m.get_mut("3").unwrap() += 100;
+        m.insert("4".to_string(), 400);
+        assert_eq!(fp, m.fingerprint());
+
+        // Neither do insertion order and capacity.
+        let mut other = HashMap::with_capacity(1024);
+        for i in (0..10).rev() {
+            other.insert(i.to_string(), i);
+        }
+        assert_eq!(fp, other.fingerprint());
+
+        m.remove("5");
+        assert_ne!(fp, m.fingerprint());
+        m.insert("5".to_string(), 5);
+        assert_eq!(fp, m.fingerprint());
+        m.insert("10".to_string(), 10);
+        assert_ne!(fp, m.fingerprint());
+    }
+
+    #[test]
+    fn test_with_random_seed() {
+        let mut a = HashMap::with_random_seed();
+        let mut b = HashMap::with_random_seed();
+        assert_ne!(a.hasher().seed(), b.hasher().seed());
+
+        for i in 0..100 {
+            a.insert(i.to_string(), i);
+            b.insert(i.to_string(), i);
+        }
+        let layout = |m: &HashMap<String, i32>| {
+            m.raw_slots()
+                .map(|s| s.map(|s| s.key.clone()))
+                .collect::<Vec<_>>()
+        };
+        assert_ne!(layout(&a), layout(&b));
+        for i in 0..100 {
+            assert_eq!(a.get(&i.to_string()), b.get(&i.to_string()));
+        }
+    }
+
+    #[test]
+    fn test_byte_key() {
+        let key = |id: u32, ts: u64| {
+            let mut b = [0_u8; 12];
+            b[..4].copy_from_slice(&id.to_be_bytes());
+            b[4..].copy_from_slice(&ts.to_be_bytes());
+            ByteKey(b)
+        };
+
+        let mut m = HashMap::new();
+        for id in 0..10 {
+            for ts in 0..10 {
+                m.insert(key(id, ts * 1000), id as u64 + ts);
+            }
+        }
+        assert_eq!(100, m.len());
+        assert_eq!(Some(&12), m.get(&key(7, 5000)));
+        assert_eq!(None, m.get(&key(7, 5001)));
+        assert_eq!(key(1, 2), ByteKey::from(key(1, 2).0));
+    }
+
+    #[test]
+    fn test_char_key() {
+        let mut m = HashMap::new();
+        for (i, c) in "héllo wörld".chars().enumerate() {
+            m.entry(CharKey::from(c)).or_insert(i);
+        }
+        assert_eq!(9, m.len());
+        assert_eq!(Some(&1), m.get(&CharKey::new('é')));
+        assert_eq!(Some(&2), m.get(&CharKey::new('l')));
+        assert_eq!(None, m.get(&CharKey::new('x')));
+
+        // The hash is that of the UTF-8 encoding, like for the string.
+        for c in ['é', 'a', '€', '😀'] {
+            let k = CharKey::new(c);
+            assert_eq!(c.len_utf8(), k.as_byte().len());
+            assert_eq!(c, k.as_char());
+            assert_eq!(hash_key(c.to_string().as_str()), hash_key(&k));
+        }
+        assert_eq!(hash_key("é"), hash_key(&CharKey::new('é')));
+    }
+
+    #[test]
+    fn test_rebuild_map() {
+        let mut m = HashMap::with_capacity(64);
+        for i in 0..40 {
+            m.insert(format!("key{}", i), i);
+        }
+
+        m.rebuild_map(|k, v| (v % 4 != 0).then(|| (k.to_uppercase(), v * 2)));
+        assert_eq!(30, m.len());
+        assert_eq!(64, m.capacity());
+        for i in 0..40 {
+            let expected = if i % 4 != 0 { Some(i * 2) } else { None };
+            assert_eq!(expected.as_ref(), m.get(&format!("KEY{}", i)));
+            assert_eq!(None, m.get(&format!("key{}", i)));
+        }
+        m.assert_invariants();
+    }
+
+    #[test]
+    fn test_clone_with_capacity() {
+        let mut m = HashMap::with_capacity(4096);
+        for i in 0..100 {
+            m.insert(i.to_string(), i);
+        }
+
+        let c = m.clone_with_capacity(200);
+        assert_eq!(256, c.capacity());
+        assert_eq!(100, c.len());
+        c.assert_invariants();
+        for i in 0..100 {
+            assert_eq!(Some(&i), c.get(&i.to_string()));
+        }
+
+        // Too small a capacity is raised to fit the elements.
+        let c = m.clone_with_capacity(16);
+        assert_eq!(128, c.capacity());
+        assert!(c.keys_equal(&m));
+    }
+
+    #[test]
+    fn test_remove_all() {
+        let mut m = HashMap::new();
+        for i in 0..10 {
+            m.insert(i.to_string(), i);
+        }
+
+        assert_eq!(3, m.remove_all(["1", "3", "5", "11", "12"]));
+        assert_eq!(7, m.len());
+        assert_eq!(None, m.get("3"));
+        assert_eq!(Some(&4), m.get("4"));
+
+        // Repeated keys count once.
+        let keys = vec!["0".to_string(), "0".to_string()];
+        assert_eq!(1, m.remove_all(&keys));
+        assert_eq!(6, m.len());
+    }
+
+    #[test]
+    fn test_new_small() {
+        let mut m = HashMap::new_small();
+        assert_eq!(HashMap::<String, u32>::SMALL_CAPACITY as u64, m.capacity());
+        assert_eq!(8, m.capacity());
+        for i in 0..20 {
+            m.insert(i.to_string(), i);
+        }
+        assert_eq!(32, m.capacity());
 
-impl<'a, K: 'a, V: 'a> Iter<'a, K, V>
-where
-    K: Eq + Hash + AsByte,
-{
-    pub fn new(map: &'a HashMap<K, V>) -> Self {
-        Self {
-            map,
-            at: 0,
-            num_found: 0,
+        let m = HashMap::<String, u32>::new();
+        assert_eq!(
+            HashMap::<String, u32>::DEFAULT_CAPACITY as u64,
+            m.capacity()
+        );
+    }
+
+    #[test]
+    fn test_aggregate() {
+        let mut m = HashMap::new();
+        m.insert("a".to_string(), "one".to_string());
+        m.insert("b".to_string(), "three".to_string());
+        m.insert("c".to_string(), String::new());
+
+        assert_eq!(8, m.aggregate(0, |n, _, v| n + v.len()));
+        assert_eq!(3, m.aggregate(0, |n, k, _| n + k.len()));
+        assert_eq!(
+            7,
+            HashMap::<String, u32>::new().aggregate(7, |n, _, _| n + 1)
+        );
+    }
+
+    #[test]
+    fn test_retain_in_place() {
+        let mut a = HashMap::new();
+        for i in 0..2000 {
+            a.insert(i.to_string(), i);
+        }
+        let mut b = HashMap::new();
+        b.extend(a.iter().map(|(k, v)| (k.clone(), *v)));
+
+        let mut calls = 0;
+        a.retain_in_place(|_, v| {
+            calls += 1;
+            *v % 3 == 0
+        });
+        b.retain(|_, v| *v % 3 == 0);
+        assert_eq!(2000, calls);
+        assert_eq!(b.len(), a.len());
+        assert_eq!(4096, a.capacity());
+        assert!(a.keys_equal(&b));
+        a.assert_invariants();
+
+        // A run that wraps around the end of the array.
+        let mut m = HashMap::with_capacity_and_hasher(16, CollidingBuilder(13));
+        for i in 0..10 {
+            m.insert(i.to_string(), i);
         }
+        let mut calls = 0;
+        m.retain_in_place(|_, v| {
+            calls += 1;
+            *v % 2 == 1
+        });
+        assert_eq!(10, calls);
+        assert_eq!(5, m.len());
+        for i in 0..10 {
+            let expected = if i % 2 == 1 { Some(&i) } else { None };
+            assert_eq!(expected, m.get(&i.to_string()));
+        }
+        m.assert_invariants();
     }
-}
 
-impl<'a, K, V> Iterator for Iter<'a, K, V>
-where
-    K: Eq + Hash + AsByte,
-{
-    type Item = (&'a K, &'a V);
+    #[test]
+    fn test_iter_disjoint_borrow() {
+        struct Index {
+            map: HashMap<String, u32>,
+            log: Vec<String>,
+            total: u32,
+        }
 
-    fn next(&mut self) -> Option<Self::Item> {
-        loop {
-            if self.at >= self.map.elems.len() {
-                return None;
+        impl Index {
+            fn record(&mut self) {
+                for (k, v) in self.map.iter() {
+                    self.log.push(format!("{}={}", k, v));
+                    self.total += v;
+                }
             }
+        }
 
-            let e = &self.map.elems[self.at];
-            self.at += 1;
+        let mut index = Index {
+            map: HashMap::new(),
+            log: Vec::new(),
+            total: 0,
+        };
+        for i in 0..10 {
+            index.map.insert(i.to_string(), i);
+        }
+        index.record();
 
-            if let Some(e) = e {
-                self.num_found += 1;
-                return Some((&e.key, &e.value));
-            }
+        assert_eq!(45, index.total);
+        index.log.sort();
+        assert_eq!("0=0", index.log[0]);
+        assert_eq!(10, index.log.len());
+    }
+
+    #[test]
+    fn test_cmp_ordered() {
+        let mut a = HashMap::with_capacity(16);
+        let mut b = HashMap::with_capacity(1024);
+        for i in 0..10 {
+            a.insert(i.to_string(), i);
+            b.insert((9 - i).to_string(), 9 - i);
         }
+        assert_eq!(Ordering::Equal, a.cmp_ordered(&b));
+
+        b.insert("5".to_string(), 50);
+        assert_eq!(Ordering::Less, a.cmp_ordered(&b));
+        assert_eq!(Ordering::Greater, b.cmp_ordered(&a));
+
+        // Elements are compared in key order, so the first difference decides:
+        // a's "2" is compared to b's "1" long before b's larger value for "5".
+        a.remove("1");
+        assert_eq!(Ordering::Greater, a.cmp_ordered(&b));
     }
 
-    fn size_hint(&self) -> (usize, Option<usize>) {
-        let hint = self.map.len() as usize - self.num_found;
-        (hint, Some(hint))
+    #[test]
+    fn test_insert_with_eviction() {
+        let mut m = HashMap::with_capacity(16);
+        let mut evicted = Vec::new();
+        for i in 0..15 {
+            m.insert_with_eviction(i.to_string(), i, |k, v| evicted.push((k, v)));
+        }
+        assert!(evicted.is_empty());
+        assert_eq!(15, m.len());
+
+        m.insert_with_eviction("15".to_string(), 15, |k, v| evicted.push((k, v)));
+        assert_eq!(1, evicted.len());
+        let (k, v) = &evicted[0];
+        assert_eq!(&k.parse::<i32>().unwrap(), v);
+        assert_eq!(None, m.get(k));
+
+        assert_eq!(Some(&15), m.get("15"));
+        assert_eq!(15, m.len());
+        assert_eq!(16, m.capacity());
+
+        // Overwriting an existing key doesn't evict.
+        assert_eq!(
+            Some(15),
+            m.insert_with_eviction("15".to_string(), 16, |_, _| unreachable!())
+        );
+        m.assert_invariants();
     }
-}
 
-pub struct Keys<'a, K: 'a, V: 'a>
-where
-    K: Eq + Hash + AsByte,
-{
-    inner: Iter<'a, K, V>,
-}
+    #[test]
+    fn test_on_grow() {
+        let grows = Arc::new(Mutex::new(Vec::new()));
+        let mut m = HashMap::with_capacity(16);
+        let log = grows.clone();
+        m.set_on_grow(Some(Box::new(move |old, new| {
+            log.lock().unwrap().push((old, new))
+        })));
 
-impl<'a, K: 'a, V: 'a> Keys<'a, K, V>
-where
-    K: Eq + Hash + AsByte,
-{
-    pub fn new(inner: Iter<'a, K, V>) -> Self {
-        Self { inner }
+        for i in 0..1000 {
+            m.insert(i.to_string(), i);
+        }
+        assert_eq!(2048, m.capacity());
+        assert_eq!(
+            vec![
+                (16, 32),
+                (32, 64),
+                (64, 128),
+                (128, 256),
+                (256, 512),
+                (512, 1024),
+                (1024, 2048)
+            ],
+            *grows.lock().unwrap()
+        );
+
+        // Explicit resizes don't count.
+        m.reserve(10_000);
+        m.set_on_grow(None);
+        for i in 1000..20_000 {
+            m.insert(i.to_string(), i);
+        }
+        assert_eq!(7, grows.lock().unwrap().len());
     }
-}
 
-impl<'a, K, V> Iterator for Keys<'a, K, V>
-where
-    K: Eq + Hash + AsByte,
-{
-    type Item = &'a K;
+    #[test]
+    fn test_index_of() {
+        let mut m = HashMap::with_capacity(64);
+        for i in 0..50 {
+            m.insert(i.to_string(), i);
+        }
 
-    fn next(&mut self) -> Option<Self::Item> {
-        match self.inner.next() {
-            Some((k, _)) => Some(k),
-            None => None,
+        let mut slots: Vec<_> = (0..50)
+            .map(|i| m.index_of(&i.to_string()).unwrap())
+            .collect();
+        assert!(slots.iter().all(|&s| (s as u64) < m.capacity()));
+        slots.sort_unstable();
+        slots.dedup();
+        assert_eq!(50, slots.len());
+        assert_eq!(None, m.index_of("50"));
+    }
+
+    #[test]
+    fn test_set_many() {
+        let mut m = HashMap::with_capacity(16);
+        m.insert("a".to_string(), 0);
+
+        let items = (0..100).map(|i| (["a", "b", "c"][i % 3].to_string(), i));
+        m.set_many(items);
+        assert_eq!(3, m.len());
+        assert_eq!(Some(&99), m.get("a"));
+        assert_eq!(Some(&97), m.get("b"));
+        assert_eq!(Some(&98), m.get("c"));
+
+        // The size hint is reserved even though most keys repeat.
+        assert_eq!(128, m.capacity());
+    }
+
+    #[test]
+    fn test_drain_filter_collect() {
+        let mut m = HashMap::new();
+        for i in 0..100 {
+            m.insert(i.to_string(), i);
+        }
+
+        let mut drained = m.drain_filter_collect(|_, v| *v >= 90);
+        drained.sort_by_key(|(_, v)| *v);
+        let expected: Vec<_> = (90..100).map(|i| (i.to_string(), i)).collect();
+        assert_eq!(expected, drained);
+
+        assert_eq!(90, m.len());
+        for i in 0..100 {
+            assert_eq!(if i < 90 { Some(&i) } else { None }, m.get(&i.to_string()));
         }
+        m.assert_invariants();
     }
-}
 
-/// hash_key computes a hash of key. Hash is always non-zero.
-pub fn hash_key<K>(key: &K) -> u64
-where
-    K: Eq + Hash + AsByte + ?Sized,
-{
-    let mut xx_hash = twox_hash::XxHash64::with_seed(0);
-    xx_hash.write(key.as_byte());
-    let mut h = xx_hash.finish();
+    #[test]
+    fn test_with_capacity_one() {
+        for capacity in [0, 1] {
+            let mut m = HashMap::with_capacity(capacity);
+            assert_eq!(2, m.capacity());
+            m.assert_invariants();
 
-    if h == 0 {
-        h = 1;
-    } else {
-        let h_ptr = &h as *const u64 as *const i64;
-        unsafe {
-            if *h_ptr < 0 {
-                // influxdb, what the fuck!!! why change the hash value?
-                h = (0 - *h_ptr) as u64;
-            }
+            m.insert("a".to_string(), 1);
+            assert_eq!(Some(&1), m.get("a"));
+            assert_eq!(None, m.get("b"));
+            m.insert("b".to_string(), 2);
+            assert_eq!(Some(&2), m.get("b"));
+            m.assert_invariants();
         }
     }
 
-    h
-}
+    #[test]
+    fn test_sorted_keys() {
+        let mut m = HashMap::new();
+        for (i, k) in ["pear", "apple", "Zebra", "banana", "app", "10", "9"]
+            .iter()
+            .enumerate()
+        {
+            m.insert(k.to_string(), i);
+        }
 
-/// distance returns the probe distance for a hash in a slot index.
-/// NOTE: Capacity must be a power of 2.
-pub fn distance(hash: u64, i: usize, capacity: u64) -> u64 {
-    let mask = capacity - 1;
-    let dist = ((i as u64) + capacity - (hash & mask)) & mask;
-    dist
-}
+        let keys = m.sorted_keys();
+        assert_eq!(
+            vec!["10", "9", "Zebra", "app", "apple", "banana", "pear"],
+            keys
+        );
+        assert!(keys.windows(2).all(|w| w[0].as_bytes() < w[1].as_bytes()));
+        assert_eq!(vec![&5, &6, &2, &4, &1, &3, &0], m.sorted_values_by_key());
+    }
 
-/// pow2 returns the number that is the next highest power of 2.
-/// Returns v if it is a power of 2.
-fn pow2(v: u64) -> u64 {
-    let mut i = 2_u64;
-    loop {
-        if i < (1_u64 << 62) {
-            if i >= v {
-                return i;
+    #[test]
+    fn test_from_keys_with() {
+        let m = HashMap::from_keys_with(["a", "bb", "ccc"].map(String::from), |k| k.len());
+        assert_eq!(3, m.len());
+        assert_eq!(Some(&1), m.get("a"));
+        assert_eq!(Some(&2), m.get("bb"));
+        assert_eq!(Some(&3), m.get("ccc"));
+
+        let m = HashMap::from_keys_with((0..1000).map(|i| i.to_string()), |k| k.len());
+        assert_eq!(2048, m.capacity());
+        assert_eq!(Some(&3), m.get("999"));
+    }
+
+    #[test]
+    fn test_is_capacity_power_of_two() {
+        let mut m = HashMap::with_capacity(16);
+        assert!(m.is_capacity_power_of_two());
+        assert!(HashMap::<String, u32>::new().is_capacity_power_of_two());
+
+        for i in 0..100 {
+            m.insert(i.to_string(), i);
+        }
+        assert_eq!(128, m.capacity());
+        assert!(m.is_capacity_power_of_two());
+
+        m.reserve(1000);
+        assert!(m.is_capacity_power_of_two());
+
+        m.retain(|_, v| *v < 10);
+        m.shrink_to_fit();
+        assert_eq!(16, m.capacity());
+        assert!(m.is_capacity_power_of_two());
+    }
+
+    #[test]
+    fn test_empty_noops() {
+        let mut m: HashMap<String, u32> = HashMap::with_capacity(64);
+        m.insert("a".to_string(), 1);
+        m.remove("a");
+        let ptr = m.elems.as_ptr();
+
+        m.retain(|_, _| unreachable!());
+        m.retain_in_place(|_, _| unreachable!());
+        m.retain_with_meta(|_, _, _| unreachable!());
+        m.rebuild_map(|_, _| unreachable!());
+        assert!(m.drain_filter_collect(|_, _| unreachable!()).is_empty());
+        m.clear();
+
+        assert_eq!(ptr, m.elems.as_ptr());
+        assert_eq!(64, m.capacity());
+        assert!(m.is_empty());
+
+        // shrink_to_fit releases the slots once, and is a no-op after that.
+        m.shrink_to_fit();
+        assert_eq!(2, m.capacity());
+        let ptr = m.elems.as_ptr();
+        m.shrink_to_fit();
+        assert_eq!(ptr, m.elems.as_ptr());
+        assert_eq!(2, m.capacity());
+    }
+
+    #[test]
+    fn test_shrink_to_empty() {
+        let mut m = HashMap::with_capacity(131072);
+        for i in 0..1000 {
+            m.insert(i.to_string(), i);
+        }
+        m.retain_and_shrink(|_, _| false);
+        assert!(m.is_empty());
+        assert_eq!(2, m.capacity());
+
+        let mut m = HashMap::with_capacity(65536);
+        m.set_shrink_threshold(Some(50));
+        m.insert("a".to_string(), 1);
+        assert_eq!(Some(1), m.remove("a"));
+        assert_eq!(2, m.capacity());
+
+        m.insert("b".to_string(), 2);
+        assert_eq!(Some(&2), m.get("b"));
+    }
+
+    #[test]
+    fn test_stats() {
+        let mut m = HashMap::with_hasher(CollidingBuilder(7));
+        for i in 0..4 {
+            m.insert(i.to_string(), i);
+        }
+
+        let stats = m.stats();
+        assert_eq!(m.len(), stats.len);
+        assert_eq!(m.capacity(), stats.capacity);
+        assert_eq!(m.load_factor(), stats.load_factor);
+        assert_eq!(m.capacity_remaining(), stats.threshold - stats.len);
+        assert_eq!(4.0 / 256.0, stats.load);
+        assert_eq!(1.5, stats.mean_probe);
+        assert_eq!(3, stats.max_probe);
+        assert!(
+            stats.memory_footprint >= 256 * std::mem::size_of::<Option<(u64, String, i32, u64)>>()
+        );
+
+        let stats = HashMap::<String, u32>::new().stats();
+        assert_eq!(0.0, stats.mean_probe);
+        assert_eq!(0, stats.max_probe);
+    }
+
+    #[test]
+    fn test_insert_reporting() {
+        let mut m = HashMap::with_hasher(CollidingBuilder(7));
+        for i in 0..4 {
+            let r = m.insert_reporting(i.to_string(), i);
+            assert_eq!(None, r.previous);
+            assert_eq!(7 + i as usize, r.slot);
+            assert_eq!(i as u64, r.probe_distance);
+            assert_eq!(Some(r.slot), m.index_of(&i.to_string()));
+        }
+
+        let r = m.insert_reporting("2".to_string(), 20);
+        assert_eq!(
+            InsertResult {
+                previous: Some(2),
+                slot: 9,
+                probe_distance: 2,
+            },
+            r
+        );
+
+        let mut m = HashMap::with_capacity(16);
+        for i in 0..100 {
+            let r = m.insert_reporting(i.to_string(), i);
+            assert_eq!(Some(r.slot), m.index_of(&i.to_string()));
+            assert_eq!(r.probe_distance + 1, m.get_with_probes(&i.to_string()).1);
+        }
+    }
+
+    #[test]
+    fn test_from_iter() {
+        let m: HashMap<String, u64> = (0..100_000).map(|i| (i.to_string(), i)).collect();
+        assert_eq!(100_000, m.len());
+        // 1 << 17 is the smallest capacity holding 100_000 elems at 90%.
+        assert_eq!(1 << 17, m.capacity());
+        for i in (0..100_000).step_by(997) {
+            assert_eq!(Some(&i), m.get(&i.to_string()));
+        }
+
+        let m: HashMap<String, u64> = (0..10).map(|i| (i.to_string(), i)).collect();
+        assert_eq!(
+            HashMap::<String, u64>::DEFAULT_CAPACITY as u64,
+            m.capacity()
+        );
+    }
+
+    #[test]
+    fn test_map_into() {
+        let mut m: HashMap<String, u32> = HashMap::with_capacity(64);
+        for i in 0..50 {
+            m.insert(i.to_string(), i);
+        }
+        let slots: Vec<_> = (0..50).map(|i| m.index_of(&i.to_string())).collect();
+
+        let mut m: HashMap<String, String> = m.map_into(|v| format!("v{}", v));
+        m.assert_invariants();
+        assert_eq!(50, m.len());
+        assert_eq!(64, m.capacity());
+        for i in 0..50 {
+            let k = i.to_string();
+            assert_eq!(Some(&format!("v{}", i)), m.get(&k));
+            assert_eq!(slots[i as usize], m.index_of(&k));
+        }
+
+        m.insert("50".to_string(), "v50".to_string());
+        assert_eq!(Some(&"v50".to_string()), m.get("50"));
+    }
+
+    #[test]
+    fn test_recount_len() {
+        let mut m = HashMap::new();
+        for i in 0..20 {
+            m.insert(i.to_string(), i);
+        }
+        m.recount_len();
+        assert_eq!(20, m.len());
+
+        m.len = 7;
+        m.recount_len();
+        assert_eq!(20, m.len());
+        m.assert_invariants();
+    }
+
+    #[test]
+    fn test_compute_with() {
+        let mut m = HashMap::new();
+        for i in 0..3 {
+            m.compute_with(i.to_string(), |m, v| {
+                assert_eq!(None, v);
+                m.len()
+            });
+        }
+        // Each value is the len before its key was inserted.
+        for i in 0..3 {
+            assert_eq!(Some(&i), m.get(&i.to_string()));
+        }
+
+        m.compute_with("1".to_string(), |m, v| m.len() * 10 + v.unwrap());
+        assert_eq!(Some(&31), m.get("1"));
+        assert_eq!(3, m.len());
+    }
+
+    #[test]
+    fn test_reference_keys() {
+        let mut m: HashMap<&str, u32> = HashMap::new();
+        m.insert("a", 1);
+        m.insert("b", 2);
+        let k: &&str = &"b";
+        assert_eq!(Some(&2), m.get(k));
+        assert_eq!(Some(&1), m.get(&"a"));
+        assert_eq!(hash_key("b"), hash_key(k));
+
+        let owned = ["x".to_string(), "y".to_string()];
+        let mut m: HashMap<&String, usize> = HashMap::new();
+        for (i, k) in owned.iter().enumerate() {
+            m.insert(k, i);
+        }
+        assert_eq!(Some(&1), m.get(&&owned[1]));
+        assert_eq!(hash_key("x"), hash_key(&&owned[0]));
+    }
+
+    #[test]
+    fn test_probe_terminates_on_corrupt_layout() {
+        // 8 slots at the default load factor take 8 elems before growing.
+        let mut m = HashMap::with_capacity_and_hasher(8, CollidingBuilder(0));
+        for i in 0..8 {
+            m.insert(i.to_string(), i);
+        }
+        assert_eq!(8, m.capacity());
+
+        // Every slot is taken and now claims to be as far from home as
+        // possible, so a probe can only stop once it has passed every slot.
+        for (pos, e) in m.elems.iter_mut().enumerate() {
+            e.as_mut().unwrap().hash = (pos as u64 + 1) % 8 + 8;
+        }
+        assert_eq!(Err((1, 8)), m.probe(9, "8"));
+        assert_eq!(None, m.get("8"));
+        assert_eq!(None, m.get("0"));
+    }
+
+    #[test]
+    fn test_put_if() {
+        let mut m = HashMap::new();
+        let keep_max = |old: &u32, new: &u32| new > old;
+
+        assert!(m.put_if("a".to_string(), 5, keep_max));
+        assert!(!m.put_if("a".to_string(), 3, keep_max));
+        assert_eq!(Some(&5), m.get("a"));
+        assert!(m.put_if("a".to_string(), 8, keep_max));
+        assert_eq!(Some(&8), m.get("a"));
+
+        let versions = [(1, 10), (2, 30), (1, 7), (2, 20), (1, 12), (3, 1)];
+        for (k, v) in versions {
+            m.put_if(k.to_string(), v, keep_max);
+        }
+        assert_eq!(Some(&12), m.get("1"));
+        assert_eq!(Some(&30), m.get("2"));
+        assert_eq!(Some(&1), m.get("3"));
+        assert_eq!(4, m.len());
+    }
+
+    #[test]
+    fn test_with_capacity_rounds_up() {
+        for (requested, capacity) in [(0, 2), (1, 2), (3, 4), (200, 256), (256, 256)] {
+            let mut m = HashMap::with_capacity(requested);
+            assert_eq!(capacity, m.capacity());
+            assert_eq!(capacity, m.mask as u64 + 1);
+            assert_eq!(capacity, m.elems.len() as u64);
+            assert_eq!(capacity * 90 / 100, m.threshold);
+
+            for i in 0..300 {
+                m.insert(i.to_string(), i);
             }
+            m.assert_invariants();
+        }
+    }
 
-            i *= 2;
-        } else {
-            break;
+    #[test]
+    fn test_byte_slice_lookup() {
+        let mut m: HashMap<Vec<u8>, i32> = HashMap::new();
+        m.insert(b"abc".to_vec(), 1);
+
+        assert_eq!(Some(&1), m.get(b"abc".as_slice()));
+        assert_eq!(Some(&1), m.get(&b"abc"[..]));
+        assert_eq!(None, m.get(b"ab".as_slice()));
+        assert_eq!(Some(1), m.remove(b"abc".as_slice()));
+    }
+
+    #[test]
+    fn test_tiered_growth() {
+        let slot = std::mem::size_of::<Option<HashElem<String, i32>>>();
+        let mut m = HashMap::with_capacity(16);
+        m.set_growth_policy(GrowthPolicy::Tiered {
+            cutoff_bytes: 128 * slot,
+            small_factor: 4,
+            large_factor: 2,
+        });
+
+        let grows = Arc::new(Mutex::new(Vec::new()));
+        let log = grows.clone();
+        m.set_on_grow(Some(Box::new(move |old, new| {
+            log.lock().unwrap().push((old, new))
+        })));
+        for i in 0..1000 {
+            m.insert(i.to_string(), i);
         }
+        m.assert_invariants();
+
+        // Below 128 slots the map quadruples, from there on it doubles.
+        assert_eq!(
+            vec![(16, 64), (64, 256), (256, 512), (512, 1024), (1024, 2048)],
+            *grows.lock().unwrap()
+        );
+
+        // Derived maps keep the policy.
+        let c = m.clone_with_capacity(0);
+        assert_eq!(m.growth_policy(), c.growth_policy());
     }
 
-    panic!("unreachable")
-}
+    #[test]
+    fn test_grown_capacity() {
+        assert_eq!(64, grown_capacity(16, 4));
+        assert_eq!(MAX_CAPACITY, grown_capacity(MAX_CAPACITY / 2, 2));
+        // A factor overshooting the maximum falls back to doubling.
+        assert_eq!(
+            MAX_CAPACITY,
+            grown_capacity(MAX_CAPACITY / 2, MAX_GROWTH_FACTOR)
+        );
+    }
 
-#[cfg(test)]
-mod tests {
-    use crate::{hash_key, HashMap};
+    #[test]
+    #[should_panic(
+        expected = "capacity 4611686018427387904 exceeds maximum of 2305843009213693952"
+    )]
+    fn test_grown_capacity_too_large() {
+        grown_capacity(MAX_CAPACITY, 2);
+    }
 
     #[test]
-    fn test_hash() {
-        let n = hash_key("xyz");
-        assert_eq!(91681375387435871, n);
+    fn test_growth_policy_rejects_invalid_factors() {
+        for factor in [0, 1, 3, 6, MAX_GROWTH_FACTOR * 2, u64::MAX] {
+            let result = std::panic::catch_unwind(|| {
+                let mut m: HashMap<String, i32> = HashMap::new();
+                m.set_growth_policy(GrowthPolicy::Tiered {
+                    cutoff_bytes: 0,
+                    small_factor: 2,
+                    large_factor: factor,
+                });
+            });
+            assert!(result.is_err(), "factor {} was accepted", factor);
+        }
+
+        let mut m = HashMap::with_capacity(16);
+        m.set_growth_policy(GrowthPolicy::Tiered {
+            cutoff_bytes: 0,
+            small_factor: 2,
+            large_factor: MAX_GROWTH_FACTOR,
+        });
+        for i in 0..16 {
+            m.insert(i.to_string(), i);
+        }
+        assert_eq!(16 * MAX_GROWTH_FACTOR, m.capacity());
     }
 
     #[test]
-    fn test_hash_map() {
+    fn test_iter_by_probe_distance() {
+        let mut m = HashMap::with_hasher(CollidingBuilder(5));
+        for i in 0..4 {
+            m.insert(i.to_string(), i);
+        }
+        let dists: Vec<_> = m
+            .iter_by_probe_distance()
+            .into_iter()
+            .map(|(k, v, d)| (k.as_str(), *v, d))
+            .collect();
+        assert_eq!(
+            vec![("0", 0, 0), ("1", 1, 1), ("2", 2, 2), ("3", 3, 3)],
+            dists
+        );
+
+        let mut m = HashMap::with_capacity(64);
+        for i in 0..57 {
+            m.insert(i.to_string(), i);
+        }
+        let elems = m.iter_by_probe_distance();
+        assert_eq!(57, elems.len());
+        assert!(elems.windows(2).all(|w| w[0].2 <= w[1].2));
+        assert!(elems.last().unwrap().2 > 0);
+        for (k, _, d) in elems {
+            let pos = m.index_of(k).unwrap();
+            assert_eq!(m.elems[pos].as_ref().unwrap().dist, d);
+        }
+    }
+
+    #[test]
+    fn test_retain() {
         let mut m = HashMap::new();
+        for i in 0..1000 {
+            m.insert(i.to_string(), i);
+        }
 
-        let size = 512;
-        for i in 0..size {
-            let key = i.to_string();
-            m.insert(key.clone(), key);
+        m.retain(|_, v| *v % 2 == 0);
+        assert_eq!(500, m.len());
+        for i in 0..1000 {
+            assert_eq!(i % 2 == 0, m.get(&i.to_string()).is_some());
         }
-        // m.print();
+    }
 
-        for i in 0..size {
-            let key = i.to_string();
-            let val = m.get(&key).unwrap();
-            assert_eq!(val.as_str(), key.as_str());
+    #[test]
+    fn test_retain_with_meta() {
+        let mut m = HashMap::with_hasher(CollidingBuilder(7));
+        for i in 0..10 {
+            m.insert(i.to_string(), i);
         }
 
-        for (k, v) in m.iter_mut() {
-            println!("{} => {}", k, v);
+        // Colliding keys line up behind their shared home slot.
+        let mut dists = Vec::new();
+        m.retain_with_meta(|_, v, dist| {
+            dists.push((*v, dist));
+            dist < 3
+        });
+        dists.sort();
+        assert_eq!((0..10).map(|i| (i, i as u64)).collect::<Vec<_>>(), dists);
+
+        assert_eq!(3, m.len());
+        for i in 0..3 {
+            assert_eq!(Some(&i), m.get(&i.to_string()));
         }
-        for (k, v) in m.iter() {
-            println!("{} => {}", k, v);
+    }
+
+    #[test]
+    fn test_retain_and_shrink() {
+        let mut m = HashMap::new();
+        for i in 0..1000 {
+            m.insert(i.to_string(), i);
+        }
+        let capacity = m.capacity();
+
+        // A small prune keeps the capacity.
+        m.retain_and_shrink(|_, v| *v >= 100);
+        assert_eq!(900, m.len());
+        assert_eq!(capacity, m.capacity());
+
+        // A big prune drops below a quarter of the capacity and shrinks.
+        m.retain_and_shrink(|_, v| *v >= 990);
+        assert_eq!(10, m.len());
+        assert!(m.capacity() < capacity);
+        for i in 990..1000 {
+            assert_eq!(Some(&i), m.get(&i.to_string()));
         }
     }
 }
@@ -0,0 +1,157 @@
+use std::hash::Hash;
+use std::iter::FusedIterator;
+
+use crate::{AsByte, HashMap, XxHash64Builder};
+
+/// words returns the number of bitmap words covering capacity slots.
+pub(crate) fn words(capacity: u64) -> usize {
+    capacity.div_ceil(64) as usize
+}
+
+impl<K, V> HashMap<K, V>
+where
+    K: Eq + Hash + AsByte,
+{
+    /// with_occupancy_bitmap creates a map with capacity slots that also keeps
+    /// a bitmap of its occupied slots, so `iter_sparse` can skip 64 empty
+    /// slots at a time. This costs a bit per slot and a little work on every
+    /// insert and remove, and only pays off for maps that are mostly empty;
+    /// see `cargo bench --bench iter`.
+    pub fn with_occupancy_bitmap(capacity: usize) -> Self {
+        let mut m = Self::with_capacity(capacity);
+        m.occupied = Some(Vec::new());
+        m.reset_occupancy();
+        m
+    }
+}
+
+impl<K, V, S> HashMap<K, V, S>
+where
+    K: Eq + Hash + AsByte,
+{
+    /// iter_sparse is like `iter`, but finds the occupied slots through the
+    /// occupancy bitmap.
+    ///
+    /// Panics if the map was not created with `with_occupancy_bitmap`.
+    pub fn iter_sparse(&self) -> SparseIter<'_, K, V, S> {
+        let bits = self
+            .occupied
+            .as_deref()
+            .expect("map has no occupancy bitmap");
+        SparseIter {
+            map: self,
+            bits,
+            word: 0,
+            cur: bits.first().copied().unwrap_or(0),
+            remaining: self.len as usize,
+        }
+    }
+
+    /// set_occupied records whether the slot at pos holds an elem, if the map
+    /// keeps an occupancy bitmap.
+    pub(crate) fn set_occupied(&mut self, pos: usize, occupied: bool) {
+        if let Some(bits) = &mut self.occupied {
+            let bit = 1 << (pos % 64);
+            if occupied {
+                bits[pos / 64] |= bit;
+            } else {
+                bits[pos / 64] &= !bit;
+            }
+        }
+    }
+
+    /// reset_occupancy marks all slots as empty, sizing the occupancy bitmap
+    /// for the current capacity, if the map keeps one.
+    pub(crate) fn reset_occupancy(&mut self) {
+        if let Some(bits) = &mut self.occupied {
+            bits.clear();
+            bits.resize(words(self.capacity), 0);
+        }
+    }
+}
+
+pub struct SparseIter<'a, K, V, S = XxHash64Builder>
+where
+    K: Eq + Hash + AsByte,
+{
+    map: &'a HashMap<K, V, S>,
+    bits: &'a [u64],
+    word: usize,
+    // cur holds the bits of word that haven't been yielded yet.
+    cur: u64,
+    remaining: usize,
+}
+
+impl<'a, K, V, S> Iterator for SparseIter<'a, K, V, S>
+where
+    K: Eq + Hash + AsByte,
+{
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.cur == 0 {
+            self.word += 1;
+            self.cur = *self.bits.get(self.word)?;
+        }
+
+        let pos = self.word * 64 + self.cur.trailing_zeros() as usize;
+        self.cur &= self.cur - 1;
+        self.remaining -= 1;
+        let e = self.map.elems[pos].as_ref().unwrap();
+        Some((&e.key, &e.value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<K, V, S> ExactSizeIterator for SparseIter<'_, K, V, S> where K: Eq + Hash + AsByte {}
+
+impl<K, V, S> FusedIterator for SparseIter<'_, K, V, S> where K: Eq + Hash + AsByte {}
+
+#[cfg(test)]
+mod tests {
+    use crate::HashMap;
+
+    #[test]
+    fn test_iter_sparse() {
+        let mut m = HashMap::with_occupancy_bitmap(1024);
+        assert_eq!(0, m.iter_sparse().count());
+
+        for i in 0..200 {
+            m.insert(i.to_string(), i);
+        }
+        for i in (0..200).step_by(3) {
+            m.remove(&i.to_string());
+        }
+        m.assert_invariants();
+
+        // Both visit the slots in order, so they agree entry for entry.
+        assert!(m.iter().eq(m.iter_sparse()));
+        assert_eq!(m.len() as usize, m.iter_sparse().len());
+
+        // Growing and clearing rebuild the bitmap.
+        for i in 200..2000 {
+            m.insert(i.to_string(), i);
+        }
+        assert_eq!(4096, m.capacity());
+        m.assert_invariants();
+        assert!(m.iter().eq(m.iter_sparse()));
+
+        m.retain(|_, v| *v % 2 == 0);
+        m.retain_in_place(|_, v| *v % 3 != 0);
+        *m.entry("x".to_string()).or_insert(0) += 1;
+        m.assert_invariants();
+        assert!(m.iter().eq(m.iter_sparse()));
+        m.clear();
+        assert_eq!(None, m.iter_sparse().next());
+    }
+
+    #[test]
+    #[should_panic(expected = "map has no occupancy bitmap")]
+    fn test_iter_sparse_without_bitmap() {
+        let m: HashMap<String, i32> = HashMap::new();
+        m.iter_sparse();
+    }
+}
@@ -0,0 +1,46 @@
+use std::hash::Hash;
+
+use rayon::prelude::*;
+
+use crate::{AsByte, HashMap};
+
+impl<K, V, S> HashMap<K, V, S>
+where
+    K: Eq + Hash + AsByte + Send,
+    V: Send,
+{
+    /// par_values_mut returns a parallel iterator over mutable references to
+    /// the values. Every slot is independent, so the slots are simply split
+    /// between the threads.
+    pub fn par_values_mut(&mut self) -> impl ParallelIterator<Item = &mut V> {
+        self.elems
+            .par_iter_mut()
+            .filter_map(|e| e.as_mut().map(|e| &mut e.value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rayon::prelude::*;
+
+    use crate::HashMap;
+
+    #[test]
+    fn test_par_values_mut() {
+        let mut m = HashMap::new();
+        for i in 0..10_000_u64 {
+            m.insert(i.to_string(), i);
+        }
+        let mut serial = HashMap::new();
+        for (k, v) in m.iter() {
+            serial.insert(k.clone(), *v);
+        }
+        serial.iter_mut().for_each(|(_, v)| *v *= 2);
+
+        m.par_values_mut().for_each(|v| *v *= 2);
+        assert_eq!(serial.len(), m.len());
+        for (k, v) in serial.iter() {
+            assert_eq!(Some(v), m.get(k));
+        }
+    }
+}
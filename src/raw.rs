@@ -0,0 +1,236 @@
+use std::hash::{BuildHasher, Hash};
+
+use crate::{
+    check_load_factor, distance, AsByte, GrowthPolicy, HashElem, HashMap, XxHash64Builder,
+};
+
+/// RawSlot is a snapshot of an occupied slot: the stored hash, the distance
+/// from the slot the hash maps to, and the element. `raw_slots` yields it with
+/// borrowed keys and values, so a serializer can capture the exact layout of
+/// a map.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RawSlot<K, V> {
+    pub hash: u64,
+    pub dist: u64,
+    pub key: K,
+    pub value: V,
+}
+
+impl<K, V> RawSlot<K, V> {
+    /// as_ref borrows the key and value of an owned slot.
+    pub fn as_ref(&self) -> RawSlot<&K, &V> {
+        RawSlot {
+            hash: self.hash,
+            dist: self.dist,
+            key: &self.key,
+            value: &self.value,
+        }
+    }
+}
+
+impl<K, V> HashMap<K, V>
+where
+    K: Eq + Hash + AsByte,
+{
+    /// from_raw_slots restores a map from a layout captured with `raw_slots`,
+    /// without hashing any key. Debug builds check that the layout is
+    /// consistent.
+    ///
+    /// # Safety
+    ///
+    /// slots must hold capacity slots, capacity must be a power of 2, len must
+    /// be the number of occupied slots, and every slot must sit at the
+    /// position its hash and distance say. The stored hashes must be the ones
+    /// `hash_key` computes with seed; otherwise lookups silently miss.
+    ///
+    /// Panics if load_factor is not within `1..100`.
+    pub unsafe fn from_raw_slots(
+        slots: Vec<Option<RawSlot<K, V>>>,
+        len: u64,
+        capacity: usize,
+        load_factor: usize,
+        seed: u64,
+    ) -> Self {
+        check_load_factor(load_factor);
+        debug_assert!(capacity.is_power_of_two(), "capacity is not a power of 2");
+        debug_assert_eq!(capacity, slots.len(), "capacity doesn't match slots");
+        debug_assert_eq!(
+            len,
+            slots.iter().flatten().count() as u64,
+            "len doesn't match slots"
+        );
+
+        let elems = slots
+            .into_iter()
+            .enumerate()
+            .map(|(pos, slot)| {
+                slot.map(|s| {
+                    debug_assert_eq!(
+                        distance(s.hash, pos, capacity as u64),
+                        s.dist,
+                        "distance of slot {} is wrong",
+                        pos
+                    );
+                    HashElem::new(s.dist, s.key, s.value, s.hash)
+                })
+            })
+            .collect();
+
+        Self {
+            elems,
+            len,
+            capacity: capacity as u64,
+            threshold: (capacity as u64 * load_factor as u64) / 100,
+            mask: capacity - 1,
+            load_factor,
+            shrink_threshold: None,
+            max_probe_distance: None,
+            on_grow: None,
+            growth_policy: GrowthPolicy::Double,
+            occupied: None,
+            hash_builder: XxHash64Builder::with_seed(seed),
+            normalize: None,
+            raw_hash: false,
+        }
+    }
+}
+
+impl<K, V, S> HashMap<K, V, S>
+where
+    K: Eq + Hash + AsByte,
+    S: BuildHasher,
+{
+    /// raw_slots returns one item per slot, in slot order, with `None` for the
+    /// empty slots.
+    pub fn raw_slots(&self) -> impl ExactSizeIterator<Item = Option<RawSlot<&K, &V>>> {
+        self.elems.iter().map(|e| {
+            e.as_ref().map(|e| RawSlot {
+                hash: e.hash,
+                dist: e.dist,
+                key: &e.key,
+                value: &e.value,
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{HashMap, RawSlot, XxHash64Builder};
+
+    type Slot = Option<RawSlot<Vec<u8>, Vec<u8>>>;
+
+    fn encode<'a>(
+        slots: impl Iterator<Item = Option<RawSlot<&'a Vec<u8>, &'a Vec<u8>>>>,
+    ) -> Vec<u8> {
+        let mut buf = Vec::new();
+        for slot in slots {
+            let slot = match slot {
+                Some(slot) => slot,
+                None => {
+                    buf.push(0);
+                    continue;
+                }
+            };
+            buf.push(1);
+            buf.extend_from_slice(&slot.hash.to_be_bytes());
+            buf.extend_from_slice(&slot.dist.to_be_bytes());
+            for b in [slot.key, slot.value] {
+                buf.extend_from_slice(&(b.len() as u32).to_be_bytes());
+                buf.extend_from_slice(b);
+            }
+        }
+        buf
+    }
+
+    fn decode(mut buf: &[u8]) -> Vec<Slot> {
+        fn take<'a>(buf: &mut &'a [u8], n: usize) -> &'a [u8] {
+            let (head, tail) = buf.split_at(n);
+            *buf = tail;
+            head
+        }
+        fn take_u64(buf: &mut &[u8]) -> u64 {
+            u64::from_be_bytes(take(buf, 8).try_into().unwrap())
+        }
+        fn take_bytes(buf: &mut &[u8]) -> Vec<u8> {
+            let len = u32::from_be_bytes(take(buf, 4).try_into().unwrap());
+            take(buf, len as usize).to_vec()
+        }
+
+        let mut slots = Vec::new();
+        while !buf.is_empty() {
+            if take(&mut buf, 1)[0] == 0 {
+                slots.push(None);
+                continue;
+            }
+            slots.push(Some(RawSlot {
+                hash: take_u64(&mut buf),
+                dist: take_u64(&mut buf),
+                key: take_bytes(&mut buf),
+                value: take_bytes(&mut buf),
+            }));
+        }
+        slots
+    }
+
+    #[test]
+    fn test_raw_slots_round_trip() {
+        let mut m = HashMap::new();
+        for i in 0..500 {
+            m.insert(i.to_string().into_bytes(), vec![i as u8; i % 5]);
+        }
+        assert_eq!(m.capacity(), m.raw_slots().len() as u64);
+
+        let buf = encode(m.raw_slots());
+        let slots = decode(&buf);
+        assert_eq!(m.capacity(), slots.len() as u64);
+        assert_eq!(m.len(), slots.iter().flatten().count() as u64);
+
+        // Every slot comes back at the same position with the same contents.
+        for (orig, restored) in m.raw_slots().zip(&slots) {
+            assert_eq!(orig, restored.as_ref().map(RawSlot::as_ref));
+        }
+        assert_eq!(
+            buf,
+            encode(slots.iter().map(|s| s.as_ref().map(RawSlot::as_ref)))
+        );
+    }
+
+    #[test]
+    fn test_from_raw_slots() {
+        let mut m =
+            HashMap::with_capacity_and_hasher_and_factor(64, XxHash64Builder::with_seed(42), 80);
+        for i in 0..40 {
+            m.insert(i.to_string(), i);
+        }
+        m.remove("7");
+
+        let slots: Vec<_> = m
+            .raw_slots()
+            .map(|s| {
+                s.map(|s| RawSlot {
+                    hash: s.hash,
+                    dist: s.dist,
+                    key: s.key.clone(),
+                    value: *s.value,
+                })
+            })
+            .collect();
+        let mut restored = unsafe { HashMap::from_raw_slots(slots, m.len(), 64, 80, 42) };
+        restored.assert_invariants();
+
+        assert!(m.raw_slots().eq(restored.raw_slots()));
+        assert_eq!(m.len(), restored.len());
+        assert_eq!(m.capacity(), restored.capacity());
+        assert_eq!(m.load_factor(), restored.load_factor());
+        assert_eq!(None, restored.get("7"));
+
+        // The restored map keeps working as usual.
+        for i in 40..100 {
+            restored.insert(i.to_string(), i);
+        }
+        for i in (0..100).filter(|i| *i != 7) {
+            assert_eq!(Some(&i), restored.get(&i.to_string()));
+        }
+    }
+}
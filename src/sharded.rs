@@ -0,0 +1,133 @@
+use std::borrow::Borrow;
+use std::fmt::{Debug, Formatter};
+use std::hash::Hash;
+
+use crate::{hash_key, AsByte, HashMap, XxHash64Builder};
+
+/// SHARD_SEED seeds the hashers of the shards. Shards are picked by `hash_key`,
+/// so if the shards hashed with the same seed, all keys of a shard would share
+/// the low bits of their hash and crowd into a fraction of its slots.
+const SHARD_SEED: u64 = 1;
+
+/// ShardedHashMap partitions its elements over a fixed number of independent
+/// maps, picking the shard of a key by `hash_key(key) % shards`. The shards
+/// can be handed out separately, e.g. to lock them individually or to process
+/// them in parallel.
+pub struct ShardedHashMap<K, V>
+where
+    K: Eq + Hash + AsByte,
+{
+    shards: Vec<HashMap<K, V>>,
+}
+
+impl<K, V> ShardedHashMap<K, V>
+where
+    K: Eq + Hash + AsByte,
+{
+    /// new creates a map with the given number of shards.
+    ///
+    /// Panics if shards is zero.
+    pub fn new(shards: usize) -> Self {
+        assert!(shards > 0, "shards must be positive");
+        let shards = (0..shards)
+            .map(|_| HashMap::with_hasher(XxHash64Builder::with_seed(SHARD_SEED)))
+            .collect();
+        Self { shards }
+    }
+
+    /// shard_of returns the index of the shard holding key.
+    pub fn shard_of<Q>(&self, key: &Q) -> usize
+    where
+        Q: ?Sized + Eq + Hash + AsByte,
+    {
+        (hash_key(key) % self.shards.len() as u64) as usize
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        Q: ?Sized + Eq + Hash + AsByte,
+        K: Borrow<Q>,
+    {
+        self.shards[self.shard_of(key)].get(key)
+    }
+
+    /// insert inserts val for key, and returns the previous value if the key
+    /// was already present.
+    pub fn insert(&mut self, key: K, val: V) -> Option<V> {
+        let i = self.shard_of(&key);
+        self.shards[i].insert(key, val)
+    }
+
+    /// remove removes key from the map, and returns its value if it was
+    /// present.
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        Q: ?Sized + Eq + Hash + AsByte,
+        K: Borrow<Q>,
+    {
+        let i = self.shard_of(key);
+        self.shards[i].remove(key)
+    }
+
+    pub fn shards(&self) -> &[HashMap<K, V>] {
+        &self.shards
+    }
+
+    /// shards_mut returns the shards for partitioned processing. Elements must
+    /// not be moved between shards, or they can no longer be found.
+    pub fn shards_mut(&mut self) -> &mut [HashMap<K, V>] {
+        &mut self.shards
+    }
+
+    pub fn len(&self) -> u64 {
+        self.shards.iter().map(HashMap::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.shards.iter().all(HashMap::is_empty)
+    }
+}
+
+impl<K, V> Debug for ShardedHashMap<K, V>
+where
+    K: Eq + Hash + AsByte + Debug,
+    V: Debug,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_map()
+            .entries(self.shards.iter().flat_map(HashMap::iter))
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ShardedHashMap;
+
+    #[test]
+    fn test_sharded() {
+        let mut m = ShardedHashMap::new(4);
+        assert!(m.is_empty());
+        for i in 0..1000 {
+            m.insert(i.to_string(), i);
+        }
+        assert_eq!(1000, m.len());
+
+        for i in 0..1000 {
+            let k = i.to_string();
+            let shard = m.shard_of(k.as_str());
+            assert_eq!(shard, m.shard_of(&k));
+            assert_eq!(Some(&i), m.shards()[shard].get(&k));
+            assert_eq!(Some(&i), m.get(&k));
+        }
+
+        // Every shard gets a share, and the shards add up to the whole.
+        assert!(m.shards().iter().all(|s| s.len() > 100));
+        assert_eq!(1000, m.shards().iter().map(|s| s.len()).sum::<u64>());
+
+        assert_eq!(Some(7), m.remove("7"));
+        assert_eq!(None, m.remove("7"));
+        assert_eq!(None, m.get("7"));
+        assert_eq!(999, m.len());
+    }
+}
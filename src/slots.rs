@@ -0,0 +1,120 @@
+//! The Robin Hood algorithms on a slot array, shared by the maps that own
+//! one. The length of the array must be a power of 2.
+
+use std::hash::Hash;
+use std::mem;
+
+use crate::{distance, HashElem};
+
+/// probe searches the elem with hash for which eq returns true. Returns `Ok`
+/// with its position if it exists, otherwise `Err` with the position and
+/// probe distance at which it would be inserted.
+pub(crate) fn probe<K, V, F>(
+    elems: &[Option<HashElem<K, V>>],
+    hash: u64,
+    mut eq: F,
+) -> Result<usize, (usize, u64)>
+where
+    K: Eq + Hash,
+    F: FnMut(&K) -> bool,
+{
+    let capacity = elems.len() as u64;
+    let mask = elems.len() - 1;
+    let mut pos = (hash & mask as u64) as usize;
+
+    let mut dist = 0_u64;
+    // No distance computed from a stored hash reaches capacity, so the check
+    // below ends the probe within capacity slots even if the layout is corrupt.
+    loop {
+        let e = match elems[pos].as_ref() {
+            Some(e) => e,
+            None => return Err((pos, dist)),
+        };
+        if dist > distance(e.hash, pos, capacity) {
+            return Err((pos, dist));
+        } else if e.hash == hash && eq(&e.key) {
+            return Ok(pos);
+        }
+
+        pos = (pos + 1) & mask;
+        dist += 1;
+    }
+}
+
+/// insert places entry at the insertion point pos found by `probe`, shifting
+/// displaced elems toward their next free slot. Returns the position of the
+/// slot that was free before. There must be one.
+pub(crate) fn insert<K, V>(
+    elems: &mut [Option<HashElem<K, V>>],
+    pos: usize,
+    dist: u64,
+    mut entry: HashElem<K, V>,
+) -> usize
+where
+    K: Eq + Hash,
+{
+    let capacity = elems.len() as u64;
+    let mask = elems.len() - 1;
+    let mut pos = pos;
+    let mut dist = dist;
+
+    // Continue searching until we find an empty slot or lower probe distance.
+    loop {
+        // Empty slot found, insert and exit.
+        entry.dist = dist;
+        let e = match &mut elems[pos] {
+            Some(e) => e,
+            None => {
+                elems[pos] = Some(entry);
+                return pos;
+            }
+        };
+
+        // If the existing elem has probed less than us, then swap places with
+        // existing elem, and keep going to find another slot for that elem.
+        let elem_dist = distance(e.hash, pos, capacity);
+        if elem_dist < dist {
+            mem::swap(e, &mut entry);
+
+            // Update current distance.
+            dist = elem_dist;
+        }
+
+        pos = (pos + 1) & mask;
+        dist += 1;
+    }
+}
+
+/// remove removes the elem at pos, then shifts the following displaced elems
+/// back by one slot so no probe chain is broken, calling on_shift with the
+/// position of every elem before it is shifted. Returns the elem and the
+/// position of the slot that is free afterwards.
+pub(crate) fn remove<K, V, F>(
+    elems: &mut [Option<HashElem<K, V>>],
+    pos: usize,
+    mut on_shift: F,
+) -> (HashElem<K, V>, usize)
+where
+    K: Eq + Hash,
+    F: FnMut(usize),
+{
+    let capacity = elems.len() as u64;
+    let mask = elems.len() - 1;
+    let removed = elems[pos].take().unwrap();
+
+    // Continue shifting until we find an empty slot or an elem at its home slot.
+    let mut pos = pos;
+    loop {
+        let next = (pos + 1) & mask;
+        match &elems[next] {
+            Some(e) if distance(e.hash, next, capacity) > 0 => {}
+            _ => return (removed, pos),
+        }
+
+        on_shift(next);
+        let mut e = elems[next].take().unwrap();
+        e.dist -= 1;
+        elems[pos] = Some(e);
+        pos = next;
+    }
+}
@@ -0,0 +1,49 @@
+//! entry_bytes promises to allocate the owned key only for vacant entries.
+//! Checking that needs a counting global allocator, which lives in its own
+//! test binary so it doesn't replace the allocator of the library tests.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+use rhh::HashMap;
+
+thread_local! {
+    static ALLOCS: Cell<usize> = const { Cell::new(0) };
+}
+
+/// CountingAlloc counts the allocations made by the current thread.
+struct CountingAlloc;
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let _ = ALLOCS.try_with(|c| c.set(c.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+fn alloc_count() -> usize {
+    ALLOCS.with(|c| c.get())
+}
+
+#[test]
+fn test_entry_bytes() {
+    let mut m: HashMap<Vec<u8>, u32> = HashMap::new();
+    *m.entry_bytes(b"abc").or_insert(0) += 1;
+    assert_eq!(Some(&1), m.get(b"abc".as_slice()));
+
+    let before = alloc_count();
+    *m.entry_bytes(b"abc").or_insert(0) += 1;
+    assert_eq!(before, alloc_count());
+    assert_eq!(Some(&2), m.get(b"abc".as_slice()));
+
+    // The vacant path allocates the owned key.
+    m.entry_bytes(b"xyz").or_insert(0);
+    assert!(alloc_count() > before);
+}